@@ -0,0 +1,17 @@
+-- Announce every change to a book on the books_changed channel, including
+-- writes made directly in SQL, so in-process caches can be invalidated.
+CREATE OR REPLACE FUNCTION notify_books_changed() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('books_changed', OLD.id::text);
+    ELSE
+        PERFORM pg_notify('books_changed', NEW.id::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS books_changed ON books;
+CREATE TRIGGER books_changed
+    AFTER INSERT OR UPDATE OR DELETE ON books
+    FOR EACH ROW EXECUTE FUNCTION notify_books_changed();
@@ -0,0 +1,15 @@
+-- Hard ceiling matching the default MAX_STOCK. Over-limit rows are reported rather than changed:
+-- stock is inventory, so the operator decides what each of those books should really hold
+-- (e.g. `UPDATE books SET stock = 100000 WHERE id IN (...)`) before rerunning the migration.
+DO $$
+DECLARE
+    over_limit TEXT;
+BEGIN
+    SELECT string_agg(id::text, ', ' ORDER BY id) INTO over_limit FROM books WHERE stock > 100000;
+    IF over_limit IS NOT NULL THEN
+        RAISE EXCEPTION 'books_stock_max: stock of these books exceeds 100000, fix them before migrating: %', over_limit;
+    END IF;
+END $$;
+
+ALTER TABLE books DROP CONSTRAINT IF EXISTS books_stock_max;
+ALTER TABLE books ADD CONSTRAINT books_stock_max CHECK (stock <= 100000);
@@ -0,0 +1,18 @@
+-- Trigram index so the autocomplete `title ILIKE 'prefix%'` lookup does not scan every book.
+-- pg_trgm is a trusted extension, so the database owner can install it. When missing it goes into
+-- `public`, because migrations run with `search_path` set to `DB_SCHEMA` alone: otherwise it would
+-- land in the first tenant's schema and be invisible to every other tenant. An existing install is
+-- used wherever it lives (managed Postgres often keeps extensions in `extensions`), so the operator
+-- class is looked up from the extension's schema rather than assumed to be in `public`.
+CREATE EXTENSION IF NOT EXISTS pg_trgm SCHEMA public;
+
+DO $$
+DECLARE
+    trgm_schema NAME;
+BEGIN
+    SELECT n.nspname INTO STRICT trgm_schema
+    FROM pg_extension e JOIN pg_namespace n ON n.oid = e.extnamespace
+    WHERE e.extname = 'pg_trgm';
+
+    EXECUTE format('CREATE INDEX IF NOT EXISTS books_title_trgm ON books USING gin (title %I.gin_trgm_ops)', trgm_schema);
+END $$;
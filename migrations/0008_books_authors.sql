@@ -0,0 +1,8 @@
+-- Co-authored books: `authors` holds every author in credit order. `author` is kept for
+-- existing clients and queries, now derived from the first entry so the two cannot disagree.
+ALTER TABLE books ADD COLUMN authors TEXT[] NOT NULL DEFAULT '{}';
+-- Re-checks every constraint on the rows it touches; 0006 only applied once books_stock_max held
+UPDATE books SET authors = ARRAY[author] WHERE author IS NOT NULL;
+
+ALTER TABLE books DROP COLUMN author;
+ALTER TABLE books ADD COLUMN author VARCHAR(255) GENERATED ALWAYS AS (authors[1]) STORED;
@@ -0,0 +1,2 @@
+-- Normalized ISBN-10 or ISBN-13 (digits only, uppercase X check digit)
+ALTER TABLE books ADD COLUMN isbn VARCHAR(13);
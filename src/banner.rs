@@ -0,0 +1,35 @@
+use std::net::SocketAddr;
+use sqlx::postgres::PgConnectOptions;
+
+use crate::{ AppState, features::Features };
+
+// Log the effective configuration once the server is listening. Only the connection settings
+// that identify the server are logged, never the password.
+pub fn log(address: SocketAddr, database: &PgConnectOptions, features: Features, state: &AppState) {
+    let pool = state.pool.options();
+
+    tracing::info!(
+        %address,
+        database.host = database.get_host(),
+        database.port = database.get_port(),
+        database.name = database.get_database(),
+        database.user = database.get_username(),
+        database.ssl_mode = ?database.get_ssl_mode(),
+        pool.max_connections = pool.get_max_connections(),
+        pool.min_connections = pool.get_min_connections(),
+        pool.test_before_acquire = pool.get_test_before_acquire(),
+        pool.idle_timeout_secs = pool.get_idle_timeout().map_or(0, |timeout| timeout.as_secs()),
+        pool.max_lifetime_secs = pool.get_max_lifetime().map_or(0, |lifetime| lifetime.as_secs()),
+        features.metrics = features.metrics,
+        features.schema = features.schema,
+        features.covers = features.covers,
+        features.search = features.search,
+        default_sort = %state.default_sort,
+        max_stock = state.max_stock,
+        feed_size = state.feed_size,
+        result_cap = state.result_cap,
+        public_base_url = %state.public_base_url,
+        "Server running on http://{}",
+        address
+    );
+}
@@ -0,0 +1,51 @@
+use axum::{ http::header, response::{ IntoResponse, Response } };
+use chrono::Datelike;
+
+use crate::Book;
+
+pub const CONTENT_TYPE: &str = "application/x-bibtex";
+
+// Render a book as a BibTeX response
+pub fn response(book: &Book) -> Response {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], entry(book)).into_response()
+}
+
+// Build a `@book{...}` entry keyed by the book ID
+fn entry(book: &Book) -> String {
+    let mut fields = vec![("title", escape(&book.title))];
+    if !book.authors.is_empty() {
+        // BibTeX separates co-authors with "and"
+        fields.push(("author", escape(&book.authors.join(" and "))));
+    }
+    if let Some(date) = book.published_date {
+        fields.push(("year", date.year().to_string()));
+    }
+    if let Some(isbn) = &book.isbn {
+        fields.push(("isbn", isbn.clone()));
+    }
+
+    let mut entry = format!("@book{{book{},\n", book.id);
+    for (name, value) in fields {
+        entry.push_str(&format!("  {} = {{{}}},\n", name, value));
+    }
+    entry.push_str("}\n");
+    entry
+}
+
+// Escape characters that have special meaning inside a BibTeX field
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,69 @@
+use std::{ num::NonZeroUsize, sync::Mutex };
+use lru::LruCache;
+
+use crate::{ Book, notify::BookChange };
+
+// Least-recently-used cache of single books keyed by ID, disabled when the capacity is 0
+pub struct BookCache {
+    entries: Option<Mutex<Entries>>,
+}
+
+struct Entries {
+    books: LruCache<i32, Book>,
+    // Bumped by every invalidation, so a read that started before one cannot cache what it read
+    generation: u64,
+}
+
+impl BookCache {
+    pub fn new(capacity: usize) -> Self {
+        BookCache {
+            entries: NonZeroUsize::new(capacity).map(|capacity|
+                Mutex::new(Entries { books: LruCache::new(capacity), generation: 0 })
+            ),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.entries.is_some()
+    }
+
+    pub fn get(&self, id: i32) -> Option<Book> {
+        let entries = self.entries.as_ref()?;
+        let book = entries.lock().unwrap().books.get(&id).cloned();
+        if book.is_some() {
+            metrics::counter!("book_cache_hits_total").increment(1);
+        } else {
+            metrics::counter!("book_cache_misses_total").increment(1);
+        }
+        book
+    }
+
+    // Taken before reading a book from the database and handed back to `insert`
+    pub fn generation(&self) -> u64 {
+        self.entries.as_ref().map_or(0, |entries| entries.lock().unwrap().generation)
+    }
+
+    // Cache `book` unless something was invalidated since `generation` was taken, in which case
+    // it may be older than a write that has already committed
+    pub fn insert(&self, book: &Book, generation: u64) {
+        if let Some(entries) = &self.entries {
+            let mut entries = entries.lock().unwrap();
+            if entries.generation == generation {
+                entries.books.put(book.id, book.clone());
+            }
+        }
+    }
+
+    pub fn invalidate(&self, change: BookChange) {
+        if let Some(entries) = &self.entries {
+            let mut entries = entries.lock().unwrap();
+            entries.generation += 1;
+            match change {
+                BookChange::Book(id) => {
+                    entries.books.pop(&id);
+                }
+                BookChange::All => entries.books.clear(),
+            }
+        }
+    }
+}
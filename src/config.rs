@@ -0,0 +1,21 @@
+use std::{ env, fmt::Display, str::FromStr };
+
+// Read an optional setting, panicking at startup if it is present but unparseable
+pub fn env_optional<T>(name: &str) -> Option<T> where T: FromStr, T::Err: Display {
+    let value = env::var(name).ok().filter(|value| !value.trim().is_empty())?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(e) => panic!("{} has an invalid value {:?}: {}", name, value, e),
+    }
+}
+
+// Read a `true`/`false` (or `1`/`0`) flag
+pub fn env_flag(name: &str, default: bool) -> bool {
+    match env::var(name).ok().map(|value| value.trim().to_ascii_lowercase()) {
+        None => default,
+        Some(value) if value.is_empty() => default,
+        Some(value) if value == "true" || value == "1" => true,
+        Some(value) if value == "false" || value == "0" => false,
+        Some(value) => panic!("{} must be true or false, got {:?}", name, value),
+    }
+}
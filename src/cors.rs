@@ -0,0 +1,47 @@
+use std::{ env, time::Duration };
+use axum::http::{ HeaderName, HeaderValue, Method, header };
+use tower_http::cors::{ AllowOrigin, CorsLayer };
+
+use crate::{ DEDUPLICATED_HEADER, config::{ env_flag, env_optional }, pagination::TRUNCATED_HEADER };
+
+// Build the CORS layer from `CORS_ALLOWED_ORIGINS`, `CORS_ALLOW_CREDENTIALS` and `CORS_MAX_AGE`
+pub fn layer() -> CorsLayer {
+    let origins: Vec<String> = env
+        ::var("CORS_ALLOWED_ORIGINS")
+        .unwrap_or_else(|_| "*".to_string())
+        .split(',')
+        .map(|origin| origin.trim().to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect();
+    let wildcard = origins.is_empty() || origins.iter().any(|origin| origin == "*");
+    let allow_credentials = env_flag("CORS_ALLOW_CREDENTIALS", false);
+
+    // Browsers refuse credentialed responses that allow any origin
+    if allow_credentials && wildcard {
+        panic!("CORS_ALLOW_CREDENTIALS requires CORS_ALLOWED_ORIGINS to list explicit origins instead of *");
+    }
+
+    let allow_origin = if wildcard {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            origins.iter().map(|origin| {
+                HeaderValue::from_str(origin).unwrap_or_else(|_| panic!("Invalid CORS origin {:?}", origin))
+            })
+        )
+    };
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+        // Let browser clients see that a create was deduplicated or a list was truncated
+        .expose_headers([HeaderName::from_static(DEDUPLICATED_HEADER), HeaderName::from_static(TRUNCATED_HEADER)])
+        .allow_credentials(allow_credentials);
+
+    if let Some(max_age) = env_optional::<u64>("CORS_MAX_AGE") {
+        layer = layer.max_age(Duration::from_secs(max_age));
+    }
+
+    layer
+}
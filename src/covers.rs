@@ -0,0 +1,102 @@
+use axum::{
+    extract::{ Multipart, State, multipart::{ MultipartError, MultipartRejection } },
+    http::{ StatusCode, header },
+    response::{ IntoResponse, Response },
+};
+use sqlx::prelude::FromRow;
+
+use crate::{ AppState, book_not_found, error::{ ApiError, BookId }, i18n::Message, read_only::Writable, repository };
+
+// Largest cover image accepted, in bytes
+pub const MAX_COVER_BYTES: usize = 5 * 1024 * 1024;
+
+// Multipart field the image is expected in
+const COVER_FIELD: &str = "cover";
+
+#[derive(FromRow)]
+pub struct Cover {
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(rejection: MultipartRejection) -> Self {
+        ApiError::BadRequest(Message::Rejection(rejection.body_text()))
+    }
+}
+
+impl From<MultipartError> for ApiError {
+    fn from(e: MultipartError) -> Self {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            cover_too_large()
+        } else {
+            ApiError::BadRequest(Message::Rejection(e.body_text()))
+        }
+    }
+}
+
+// Upload or replace a book's cover image
+pub async fn upload_cover(
+    State(state): State<AppState>,
+    _: Writable,
+    BookId(id): BookId,
+    multipart: Result<Multipart, MultipartRejection>
+) -> Result<StatusCode, ApiError> {
+    let mut multipart = multipart?;
+
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() != Some(COVER_FIELD) {
+            continue;
+        }
+
+        let declared = field.content_type().unwrap_or_default().to_string();
+        let data = field.bytes().await?;
+        if data.len() > MAX_COVER_BYTES {
+            return Err(cover_too_large());
+        }
+
+        // Trust the file contents over the declared type, but require both to agree
+        let content_type = match sniff_image_type(&data) {
+            Some(sniffed) if sniffed == declared => sniffed,
+            _ => {
+                return Err(ApiError::BadRequest(Message::CoverType));
+            }
+        };
+
+        return if repository::save_cover(&state.pool, id, content_type, &data).await? {
+            Ok(StatusCode::NO_CONTENT)
+        } else {
+            Err(book_not_found(id))
+        };
+    }
+
+    Err(ApiError::BadRequest(Message::MissingField { name: COVER_FIELD }))
+}
+
+// Serve a book's cover image
+pub async fn get_cover(State(state): State<AppState>, BookId(id): BookId) -> Result<Response, ApiError> {
+    let cover = repository
+        ::find_cover(&state.pool, id).await?
+        .ok_or(ApiError::NotFound(Message::NoCover { id }))?;
+
+    Ok(([(header::CONTENT_TYPE, cover.content_type)], cover.data).into_response())
+}
+
+// Detect the image format from its magic bytes
+fn sniff_image_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+fn cover_too_large() -> ApiError {
+    ApiError::PayloadTooLarge(Message::CoverTooLarge { max: MAX_COVER_BYTES })
+}
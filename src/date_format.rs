@@ -0,0 +1,48 @@
+use serde::{ Deserialize, Serialize, Serializer, ser::Error };
+use serde_json::Value;
+
+#[cfg(not(feature = "camel-case"))]
+const PUBLISHED_DATE: &str = "published_date";
+#[cfg(feature = "camel-case")]
+const PUBLISHED_DATE: &str = "publishedDate";
+
+// How `published_date` is written in responses, chosen with `?date_format=`
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateFormat {
+    // `"1997-06-26"`
+    #[default]
+    Iso,
+    // `"1997"`
+    Year,
+}
+
+// Serializes a book, a book with relations, or a list of either with `published_date` in
+// the requested format. Other fields are written exactly as `T` writes them.
+pub struct DateFormatted<T>(pub T, pub DateFormat);
+
+impl<T: Serialize> Serialize for DateFormatted<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let DateFormatted(value, format) = self;
+        if *format == DateFormat::Iso {
+            return value.serialize(serializer);
+        }
+
+        let mut value = serde_json::to_value(value).map_err(S::Error::custom)?;
+        match &mut value {
+            Value::Array(items) => items.iter_mut().for_each(to_year),
+            item => to_year(item),
+        }
+        value.serialize(serializer)
+    }
+}
+
+// Cut an ISO `published_date` down to its year, leaving `null` alone
+fn to_year(item: &mut Value) {
+    if let Some(Value::String(date)) = item.get_mut(PUBLISHED_DATE) {
+        // Split from the right so years before 1 CE keep their sign
+        if let Some(year) = date.rsplitn(3, '-').nth(2) {
+            *date = year.to_string();
+        }
+    }
+}
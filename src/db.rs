@@ -0,0 +1,130 @@
+use std::{ env, time::Duration };
+use sqlx::{ Executor, PgPool, postgres::{ PgConnectOptions, PgPoolOptions, PgSslMode } };
+
+use crate::config::{ env_flag, env_optional };
+
+// Same as sqlx's defaults
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 600;
+const DEFAULT_MAX_LIFETIME_SECS: u64 = 1800;
+
+// Connection settings from `DATABASE_URL`, or when it is absent from `DB_HOST`, `DB_PORT`,
+// `DB_USER`, `DB_PASSWORD`, `DB_NAME` and `DB_SSLMODE`. Unset `DB_*` values fall back to the
+// standard libpq `PG*` variables and then to libpq's defaults, so an environment providing
+// only `PGHOST`, `PGUSER`, ... works as well.
+pub fn connect_options() -> PgConnectOptions {
+    if let Some(url) = env::var("DATABASE_URL").ok().filter(|url| !url.trim().is_empty()) {
+        return url.parse().unwrap_or_else(|e| panic!("DATABASE_URL is invalid: {}", e));
+    }
+
+    let mut options = PgConnectOptions::new();
+    if let Ok(host) = env::var("DB_HOST") {
+        options = options.host(&host);
+    }
+    if let Some(port) = env_optional::<u16>("DB_PORT") {
+        options = options.port(port);
+    }
+    if let Ok(user) = env::var("DB_USER") {
+        options = options.username(&user);
+    }
+    if let Ok(password) = env::var("DB_PASSWORD") {
+        options = options.password(&password);
+    }
+    if let Ok(name) = env::var("DB_NAME") {
+        options = options.database(&name);
+    }
+    // disable, allow, prefer, require, verify-ca or verify-full
+    if let Some(ssl_mode) = env_optional::<PgSslMode>("DB_SSLMODE") {
+        options = options.ssl_mode(ssl_mode);
+    }
+    options
+}
+
+// `DB_SCHEMA` (default `public`), checked to be a plain identifier so it can be quoted safely
+pub fn schema() -> String {
+    let schema = env::var("DB_SCHEMA").unwrap_or_else(|_| "public".to_string());
+    if !is_identifier(&schema) {
+        panic!("DB_SCHEMA must be a plain identifier (letters, digits and underscores), got {:?}", schema);
+    }
+    schema
+}
+
+// Connect to the database with every connection scoped to `schema`.
+// Stale connections are caught by a ping before each checkout (`DB_TEST_BEFORE_ACQUIRE`) and
+// recycled after `DB_IDLE_TIMEOUT_SECS` idle or `DB_MAX_LIFETIME_SECS` total; 0 disables either limit.
+pub async fn connect(options: PgConnectOptions, schema: &str) -> PgPool {
+    // Validated by `schema`, so quoting it cannot break out of the identifier
+    let set_search_path = format!("SET search_path TO \"{}\"", schema);
+    PgPoolOptions::new()
+        .test_before_acquire(env_flag("DB_TEST_BEFORE_ACQUIRE", true))
+        .idle_timeout(seconds(env_optional("DB_IDLE_TIMEOUT_SECS").unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS)))
+        .max_lifetime(seconds(env_optional("DB_MAX_LIFETIME_SECS").unwrap_or(DEFAULT_MAX_LIFETIME_SECS)))
+        .after_connect(move |conn, _meta| {
+            let set_search_path = set_search_path.clone();
+            Box::pin(async move {
+                conn.execute(set_search_path.as_str()).await?;
+                Ok(())
+            })
+        })
+        .connect_with(options).await
+        .expect("Failed to connect to DB")
+}
+
+// Create `schema` if needed and apply pending migrations
+pub async fn migrate(pool: &PgPool, schema: &str) {
+    // Migrations run through the same search_path, so the schema has to exist first. Postgres checks
+    // the CREATE privilege on the database even with IF NOT EXISTS, so only ask when it is missing.
+    if schema != "public" {
+        let exists: bool = sqlx
+            ::query_scalar("SELECT EXISTS (SELECT 1 FROM pg_namespace WHERE nspname = $1)")
+            .bind(schema)
+            .fetch_one(pool).await
+            .expect("Failed to look up DB_SCHEMA");
+        if !exists {
+            pool
+                .execute(format!("CREATE SCHEMA IF NOT EXISTS \"{}\"", schema).as_str()).await
+                .expect("Failed to create DB_SCHEMA");
+        }
+    }
+
+    sqlx::migrate!().run(pool).await.expect("Migration failed");
+}
+
+// Read-only stand-in for `migrate`: runs no DDL, which a read-only database would refuse, and
+// only checks that every migration has already been applied
+pub async fn check_migrated(pool: &PgPool) {
+    let table: Option<String> = sqlx
+        ::query_scalar("SELECT to_regclass('_sqlx_migrations')::text")
+        .fetch_one(pool).await
+        .expect("Failed to look up applied migrations");
+    let applied: Vec<i64> = match table {
+        Some(_) =>
+            sqlx
+                ::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(pool).await
+                .expect("Failed to look up applied migrations"),
+        None => Vec::new(),
+    };
+
+    let pending: Vec<String> = sqlx
+        ::migrate!()
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration() && !applied.contains(&migration.version))
+        .map(|migration| format!("{} {}", migration.version, migration.description))
+        .collect();
+    if !pending.is_empty() {
+        panic!(
+            "READ_ONLY is set but these migrations have not been applied: {}; start once without READ_ONLY to apply them",
+            pending.join(", ")
+        );
+    }
+}
+
+fn seconds(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
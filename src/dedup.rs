@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{ Duration, Instant },
+};
+use chrono::NaiveDate;
+use tokio::sync::watch;
+
+use crate::{ Book, Bookpayload };
+
+pub enum Created {
+    New(Book),
+    // An identical payload was created within the window; this is that book
+    Duplicate(Book),
+}
+
+enum Entry {
+    // Being inserted right now; the book is sent once the insert commits
+    InFlight(watch::Receiver<Option<Book>>),
+    Created(Instant, Book),
+}
+
+// The payload as it will be stored, so whitespace differences still count as identical
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    title: String,
+    authors: Vec<String>,
+    isbn: Option<String>,
+    stock: i32,
+    published_date: Option<NaiveDate>,
+}
+
+impl Key {
+    fn new(payload: &Bookpayload) -> Self {
+        Key {
+            title: payload.title.trim().to_string(),
+            authors: payload.authors(),
+            isbn: payload.isbn().ok().flatten(),
+            stock: payload.stock,
+            published_date: payload.published_date,
+        }
+    }
+}
+
+type Entries = Mutex<HashMap<Key, Entry>>;
+
+// Remembers recently created books by payload so double-submits return the first result,
+// disabled when the window is zero
+pub struct CreateDedup {
+    window: Duration,
+    // Only held to look up or record an entry, never across an insert, so requests with
+    // different payloads do not wait for each other
+    recent: Option<Entries>,
+}
+
+impl CreateDedup {
+    pub fn new(window: Duration) -> Self {
+        CreateDedup {
+            window,
+            recent: (!window.is_zero()).then(|| Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Forget a created book after the API changed or deleted it, so an identical create inserts
+    // again instead of answering with a book that no longer looks like the payload. Not fed from
+    // `books_changed`, which also announces the insert the entry was recorded for.
+    pub fn forget(&self, id: i32) {
+        if let Some(recent) = &self.recent {
+            recent.lock().unwrap().retain(|_, entry| !matches!(entry, Entry::Created(_, book) if book.id == id));
+        }
+    }
+
+    // Run `create` unless the same payload was created within the window or is being created
+    // by another request, in which case wait for that one and return its book
+    pub async fn create<F>(&self, payload: &Bookpayload, create: F) -> Result<Created, sqlx::Error>
+        where F: Future<Output = Result<Book, sqlx::Error>>
+    {
+        let Some(recent) = &self.recent else {
+            return create.await.map(Created::New);
+        };
+
+        let key = Key::new(payload);
+        let sender = loop {
+            let mut in_flight = {
+                let mut entries = recent.lock().unwrap();
+                entries.retain(|_, entry| match entry {
+                    Entry::InFlight(_) => true,
+                    Entry::Created(created_at, _) => created_at.elapsed() < self.window,
+                });
+                match entries.get(&key) {
+                    Some(Entry::Created(_, book)) => return Ok(duplicate(book.clone())),
+                    Some(Entry::InFlight(receiver)) => receiver.clone(),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        entries.insert(key.clone(), Entry::InFlight(receiver));
+                        break sender;
+                    }
+                }
+            };
+            // The other request's sender is dropped without a book if its insert failed or it was
+            // cancelled; look again, and insert ourselves unless someone else already is
+            let book = in_flight.wait_for(Option::is_some).await.ok().and_then(|book| book.clone());
+            if let Some(book) = book {
+                return Ok(duplicate(book));
+            }
+        };
+
+        let _abandoned = Abandoned { recent, key: key.clone() };
+        let book = create.await?;
+        recent.lock().unwrap().insert(key, Entry::Created(Instant::now(), book.clone()));
+        sender.send_replace(Some(book.clone()));
+        Ok(Created::New(book))
+    }
+}
+
+fn duplicate(book: Book) -> Created {
+    metrics::counter!("book_create_deduplicated_total").increment(1);
+    Created::Duplicate(book)
+}
+
+// Clears an in-flight entry whose insert failed or was cancelled, so the next identical request
+// inserts instead of waiting on it
+struct Abandoned<'a> {
+    recent: &'a Entries,
+    key: Key,
+}
+
+impl Drop for Abandoned<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut entries) = self.recent.lock() {
+            if matches!(entries.get(&self.key), Some(Entry::InFlight(_))) {
+                entries.remove(&self.key);
+            }
+        }
+    }
+}
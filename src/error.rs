@@ -0,0 +1,150 @@
+use axum::{
+    Json,
+    extract::{ FromRequest, FromRequestParts, Path, rejection::{ JsonRejection, QueryRejection } },
+    http::{ StatusCode, request::Parts },
+    response::{ IntoResponse, Response },
+};
+use serde_json::{ Value, json };
+
+use crate::i18n::Message;
+
+// Error returned by handlers, rendered as `{"error": {"code": ..., "message": ...}}`
+#[derive(Debug)]
+pub enum ApiError {
+    // The request could not be parsed (malformed JSON, wrong types, missing fields)
+    BadRequest(Message),
+    // The request parsed fine but its content is not acceptable
+    Unprocessable(Message),
+    NotFound(Message),
+    // The route exists but does not support the method; the router adds the `Allow` header
+    MethodNotAllowed(Message),
+    // The request conflicts with the current state of the resource
+    Conflict(Message),
+    PayloadTooLarge(Message),
+    // The request is valid but the service cannot handle it right now (e.g. read-only mode)
+    ServiceUnavailable(Message),
+    Internal,
+}
+
+impl ApiError {
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            ApiError::Unprocessable(_) => (StatusCode::UNPROCESSABLE_ENTITY, "unprocessable_entity"),
+            ApiError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            ApiError::MethodNotAllowed(_) => (StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed"),
+            ApiError::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
+            ApiError::PayloadTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large"),
+            ApiError::ServiceUnavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, "service_unavailable"),
+            ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, code) = self.status_and_code();
+        let message = match self {
+            ApiError::BadRequest(message) |
+            ApiError::Unprocessable(message) |
+            ApiError::NotFound(message) |
+            ApiError::MethodNotAllowed(message) |
+            ApiError::Conflict(message) |
+            ApiError::PayloadTooLarge(message) |
+            ApiError::ServiceUnavailable(message) => message,
+            ApiError::Internal => Message::Internal,
+        };
+
+        let mut response = (status, body(code, &message.to_string())).into_response();
+        // Kept on the response so middleware such as `i18n::localize` can render it in another language
+        response.extensions_mut().insert(RenderedError { code, message });
+        response
+    }
+}
+
+// The code and message of an error response
+#[derive(Clone)]
+pub struct RenderedError {
+    pub code: &'static str,
+    pub message: Message,
+}
+
+// JSON body shared by every error response
+pub fn body(code: &str, message: &str) -> Json<Value> {
+    Json(json!({ "error": { "code": code, "message": message } }))
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        tracing::error!("Database error: {}", e);
+        ApiError::Internal
+    }
+}
+
+// Any body that cannot be turned into the payload type is malformed input
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::BadRequest(Message::Rejection(rejection.body_text()))
+    }
+}
+
+// Query strings that do not match the expected parameters are malformed input
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::BadRequest(Message::Rejection(rejection.body_text()))
+    }
+}
+
+// `Json` extractor that reports rejections as `ApiError`
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+// `Query` extractor that reports rejections as `ApiError`
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+// `{id}` path segment of a book route, reporting anything but an integer as `ApiError`
+pub struct BookId(pub i32);
+
+impl<S: Send + Sync> FromRequestParts<S> for BookId {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let id = path_segment(parts, state).await?;
+        parse_book_id(&id).map(BookId)
+    }
+}
+
+// `{id}` path segment of `GET /books/{id}`, which also serves `/books/{id}.bib` as BibTeX
+pub struct BookIdOrBib {
+    pub id: i32,
+    pub bibtex: bool,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for BookIdOrBib {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let segment = path_segment(parts, state).await?;
+        // The router cannot match a suffix after a parameter, so split it off here
+        let (id, bibtex) = match segment.strip_suffix(".bib") {
+            Some(id) => (id, true),
+            None => (segment.as_str(), false),
+        };
+        Ok(BookIdOrBib { id: parse_book_id(id)?, bibtex })
+    }
+}
+
+// The single path parameter as text; a segment that is not valid UTF-8 once decoded is a bad request
+async fn path_segment<S: Send + Sync>(parts: &mut Parts, state: &S) -> Result<String, ApiError> {
+    let Path(segment) = Path::<String>
+        ::from_request_parts(parts, state).await
+        .map_err(|rejection| ApiError::BadRequest(Message::Rejection(rejection.body_text())))?;
+    Ok(segment)
+}
+
+fn parse_book_id(id: &str) -> Result<i32, ApiError> {
+    id.parse().map_err(|_| ApiError::BadRequest(Message::InvalidBookId))
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+use sqlx::PgPool;
+use tokio::{ task::JoinHandle, time::MissedTickBehavior };
+
+use crate::{ repository, shutdown::Stopping };
+
+// Every `every`, expire active reservations older than `ttl` and give their copies back to
+// stock. Runs until shutdown begins; a run that is underway is allowed to finish.
+pub fn spawn(pool: PgPool, ttl: Duration, every: Duration, max_stock: i32, mut stopping: Stopping) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(every);
+        // After a slow run, wait a full interval rather than firing the missed ticks back to back
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {}
+                _ = stopping.wait() => break,
+            }
+
+            match repository::expire_reservations(&pool, ttl, max_stock).await {
+                Ok(outcome) => {
+                    match outcome.expired {
+                        0 => tracing::debug!("No reservations to expire"),
+                        expired => tracing::info!(expired, "Expired stale reservations"),
+                    }
+                    for book in outcome.blocked {
+                        tracing::warn!(
+                            book_id = book.book_id,
+                            reservations = book.reservations,
+                            quantity = book.quantity,
+                            max_stock,
+                            "Left stale reservations active: returning their copies would exceed MAX_STOCK"
+                        );
+                    }
+                }
+                Err(e) => tracing::error!("Reservation expiry failed: {}", e),
+            }
+        }
+        tracing::info!("Reservation expiry stopped");
+    })
+}
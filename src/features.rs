@@ -0,0 +1,22 @@
+use crate::config::env_flag;
+
+// Optional endpoints that can be switched off with `ENABLE_*=false`; disabled routes return 404
+#[derive(Clone, Copy, Debug)]
+pub struct Features {
+    pub metrics: bool,
+    pub schema: bool,
+    pub covers: bool,
+    // Title autocomplete
+    pub search: bool,
+}
+
+impl Features {
+    pub fn from_env() -> Self {
+        Features {
+            metrics: env_flag("ENABLE_METRICS", true),
+            schema: env_flag("ENABLE_SCHEMA", true),
+            covers: env_flag("ENABLE_COVERS", true),
+            search: env_flag("ENABLE_SEARCH", true),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use axum::{ extract::State, http::header, response::{ IntoResponse, Response } };
+use chrono::{ DateTime, Utc };
+use rss::{ Channel, Guid, Item, extension::dublincore::DublinCoreExtension };
+
+use crate::{ AppState, Book, error::ApiError, repository };
+
+pub const CONTENT_TYPE: &str = "application/rss+xml; charset=utf-8";
+
+// RSS feed of the most recently added books
+pub async fn new_arrivals(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let books = repository::latest(&state.pool, state.feed_size).await?;
+
+    let mut channel = Channel::default();
+    channel.set_title("Bookshelf: new arrivals");
+    channel.set_link(format!("{}/books", state.public_base_url));
+    channel.set_description("Books most recently added to the catalog");
+    if let Some(newest) = books.first() {
+        channel.set_last_build_date(rfc2822(newest));
+    }
+    channel.set_items(
+        books
+            .iter()
+            .map(|book| item(book, &state.public_base_url))
+            .collect::<Vec<_>>()
+    );
+
+    Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], channel.to_string()).into_response())
+}
+
+fn item(book: &Book, base_url: &str) -> Item {
+    let link = format!("{}/books/{}", base_url, book.id);
+
+    let mut guid = Guid::default();
+    guid.set_value(link.clone());
+    guid.set_permalink(true);
+
+    let mut item = Item::default();
+    item.set_title(book.title.clone());
+    item.set_link(link);
+    item.set_guid(guid);
+    // The feed announces arrivals, so an item is "published" when the book was added
+    item.set_pub_date(rfc2822(book));
+    if let Some(date) = book.published_date {
+        item.set_description(format!("Published {}", date));
+    }
+    // RSS <author> must be an email address, so the names go in dc:creator
+    if !book.authors.is_empty() {
+        let mut dublin_core = DublinCoreExtension::default();
+        dublin_core.set_creators(book.authors.clone());
+        item.set_dublin_core_ext(dublin_core);
+    }
+    item
+}
+
+fn rfc2822(book: &Book) -> String {
+    DateTime::<Utc>::from_naive_utc_and_offset(book.created_at, Utc).to_rfc2822()
+}
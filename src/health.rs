@@ -0,0 +1,36 @@
+use std::time::Instant;
+use axum::{ Json, extract::State, http::StatusCode };
+use serde_json::{ Value, json };
+
+use crate::{ AppState, repository };
+
+// Report connection pool usage and database round-trip latency
+pub async fn detailed(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let start = Instant::now();
+    let ping = repository::ping(&state.pool).await;
+    let latency_ms = (start.elapsed().as_secs_f64() * 1_000_000.0).round() / 1000.0;
+
+    let size = state.pool.size();
+    let idle = state.pool.num_idle() as u32;
+    let pool = json!({
+        "size": size,
+        "idle": idle,
+        "in_use": size.saturating_sub(idle),
+        "max_size": state.pool.options().get_max_connections(),
+    });
+
+    match ping {
+        Ok(()) =>
+            (
+                StatusCode::OK,
+                Json(json!({ "status": "ok", "database": { "latency_ms": latency_ms }, "pool": pool })),
+            ),
+        Err(e) => {
+            tracing::error!("Health check error: {}", e);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "unavailable", "database": { "latency_ms": null }, "pool": pool })),
+            )
+        }
+    }
+}
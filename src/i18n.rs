@@ -0,0 +1,320 @@
+use std::fmt;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{ HeaderValue, header::{ ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_LENGTH, VARY } },
+    middleware::Next,
+    response::Response,
+};
+
+use crate::error::{ self, RenderedError };
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    Indonesian,
+}
+
+impl Language {
+    fn from_tag(tag: &str) -> Option<Self> {
+        // Only the primary subtag matters: `id-ID` and `id` are both Indonesian
+        let primary = tag.split('-').next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Language::English),
+            "id" => Some(Language::Indonesian),
+            _ => None,
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Indonesian => "id",
+        }
+    }
+
+    // Pick the supported language with the highest `q` weight, falling back to English
+    pub fn negotiate(header: Option<&HeaderValue>) -> Self {
+        let Some(header) = header.and_then(|value| value.to_str().ok()) else {
+            return Language::English;
+        };
+
+        let mut best: Option<(Language, f32)> = None;
+        for entry in header.split(',') {
+            let mut parts = entry.split(';');
+            let Some(language) = Language::from_tag(parts.next().unwrap_or_default().trim()) else {
+                continue;
+            };
+            let weight = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+                .unwrap_or(0.0);
+            if weight > 0.0 && best.is_none_or(|(_, best_weight)| weight > best_weight) {
+                best = Some((language, weight));
+            }
+        }
+        best.map_or(Language::English, |(language, _)| language)
+    }
+}
+
+// Every message an error response can carry: the variant is the key, its fields the values
+// placed into the text. Matching on it exhaustively below is what keeps each one translated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    RouteNotFound,
+    MethodNotAllowed { method: String },
+    BookNotFound { id: i32 },
+    NoCover { id: i32 },
+    InvalidBookId,
+    TitleEmpty,
+    TitleTooLong { max: usize },
+    AuthorTooLong { max: usize },
+    TooManyAuthors { max: usize },
+    EmptyAuthor,
+    AuthorMismatch,
+    IsbnLength,
+    IsbnCharacters,
+    IsbnCheckDigit,
+    TooManyTags { max: usize },
+    TagLength { max: usize },
+    StockNegative,
+    StockTooHigh { max: i32 },
+    PublishedInFuture,
+    LimitOutOfRange { max: i64 },
+    UnknownInclude { name: String },
+    UnknownSort { name: String, fields: String },
+    EmptyQuery,
+    NegativeOffset,
+    DeltaOutOfRange { max: i32 },
+    AdjustConflict { id: i32, delta: i32, stock: i32, max: i32 },
+    QuantityTooLow,
+    ReserveConflict { quantity: i32, id: i32, available: i32 },
+    CheckoutSize { max: usize },
+    CheckoutQuantity { item: usize },
+    CheckoutNotFound { item: usize, id: i32 },
+    CheckoutConflict { item: usize, quantity: i32, id: i32, available: i32 },
+    CoverType,
+    MissingField { name: &'static str },
+    CoverTooLarge { max: usize },
+    ReadOnly,
+    Internal,
+    // Text produced by a library, such as an extractor rejection, which only exists in English
+    Rejection(String),
+}
+
+impl Message {
+    fn english(&self) -> String {
+        match self {
+            Message::RouteNotFound => "route not found".to_string(),
+            Message::MethodNotAllowed { method } => format!("method {} is not allowed on this route", method),
+            Message::BookNotFound { id } => format!("book {} not found", id),
+            Message::NoCover { id } => format!("book {} has no cover", id),
+            Message::InvalidBookId => "invalid book id: expected integer".to_string(),
+            Message::TitleEmpty => "title must not be empty".to_string(),
+            Message::TitleTooLong { max } => format!("title must be at most {} characters", max),
+            Message::AuthorTooLong { max } => format!("author names must be at most {} characters", max),
+            Message::TooManyAuthors { max } => format!("a book can have at most {} authors", max),
+            Message::EmptyAuthor => "authors must not contain empty names".to_string(),
+            Message::AuthorMismatch => "author must match the first entry of authors".to_string(),
+            Message::IsbnLength => "isbn must have 10 or 13 digits".to_string(),
+            Message::IsbnCharacters =>
+                "isbn must contain only digits, with an optional X check digit for ISBN-10".to_string(),
+            Message::IsbnCheckDigit => "isbn check digit is invalid".to_string(),
+            Message::TooManyTags { max } => format!("a book can have at most {} tags", max),
+            Message::TagLength { max } => format!("tag names must be between 1 and {} characters", max),
+            Message::StockNegative => "stock must not be negative".to_string(),
+            Message::StockTooHigh { max } => format!("stock must not exceed {}", max),
+            Message::PublishedInFuture => "published_date must not be in the future".to_string(),
+            Message::LimitOutOfRange { max } => format!("limit must be between 1 and {}", max),
+            Message::UnknownInclude { name } => format!("unknown include {:?}, expected reviews or tags", name),
+            Message::UnknownSort { name, fields } =>
+                format!("unknown sort field {:?}, expected one of {} (prefix with - for descending)", name, fields),
+            Message::EmptyQuery => "q must not be empty".to_string(),
+            Message::NegativeOffset => "offset must not be negative".to_string(),
+            Message::DeltaOutOfRange { max } => format!("delta must be between -{0} and {0}", max),
+            Message::AdjustConflict { id, delta, stock, max } =>
+                format!(
+                    "cannot adjust stock of book {} by {}: current stock is {} and must stay between 0 and {}",
+                    id,
+                    delta,
+                    stock,
+                    max
+                ),
+            Message::QuantityTooLow => "quantity must be at least 1".to_string(),
+            Message::ReserveConflict { quantity, id, available } =>
+                format!("cannot reserve {} copies of book {}: only {} in stock", quantity, id, available),
+            Message::CheckoutSize { max } => format!("checkout must contain between 1 and {} items", max),
+            Message::CheckoutQuantity { item } => format!("checkout item {}: quantity must be at least 1", item),
+            Message::CheckoutNotFound { item, id } => format!("checkout item {}: book {} not found", item, id),
+            Message::CheckoutConflict { item, quantity, id, available } =>
+                format!(
+                    "checkout item {}: cannot check out {} copies of book {}: only {} in stock",
+                    item,
+                    quantity,
+                    id,
+                    available
+                ),
+            Message::CoverType => "cover must be a PNG, JPEG, GIF or WebP image".to_string(),
+            Message::MissingField { name } => format!("missing multipart field `{}`", name),
+            Message::CoverTooLarge { max } => format!("cover must be at most {} bytes", max),
+            Message::ReadOnly => "the service is in read-only mode; writes are temporarily disabled".to_string(),
+            Message::Internal => "internal server error".to_string(),
+            Message::Rejection(text) => text.clone(),
+        }
+    }
+
+    fn indonesian(&self) -> Option<String> {
+        let text = match self {
+            Message::RouteNotFound => "rute tidak ditemukan".to_string(),
+            Message::MethodNotAllowed { method } => format!("metode {} tidak diizinkan pada rute ini", method),
+            Message::BookNotFound { id } => format!("buku {} tidak ditemukan", id),
+            Message::NoCover { id } => format!("buku {} tidak memiliki sampul", id),
+            Message::InvalidBookId => "ID buku tidak valid: harus berupa bilangan bulat".to_string(),
+            Message::TitleEmpty => "judul tidak boleh kosong".to_string(),
+            Message::TitleTooLong { max } => format!("judul maksimal {} karakter", max),
+            Message::AuthorTooLong { max } => format!("nama penulis maksimal {} karakter", max),
+            Message::TooManyAuthors { max } => format!("sebuah buku dapat memiliki paling banyak {} penulis", max),
+            Message::EmptyAuthor => "authors tidak boleh berisi nama kosong".to_string(),
+            Message::AuthorMismatch => "author harus sama dengan entri pertama authors".to_string(),
+            Message::IsbnLength => "isbn harus terdiri dari 10 atau 13 digit".to_string(),
+            Message::IsbnCharacters =>
+                "isbn hanya boleh berisi digit, dengan digit pemeriksa X opsional untuk ISBN-10".to_string(),
+            Message::IsbnCheckDigit => "digit pemeriksa isbn tidak valid".to_string(),
+            Message::TooManyTags { max } => format!("sebuah buku dapat memiliki paling banyak {} tag", max),
+            Message::TagLength { max } => format!("nama tag harus terdiri dari 1 sampai {} karakter", max),
+            Message::StockNegative => "stok tidak boleh negatif".to_string(),
+            Message::StockTooHigh { max } => format!("stok tidak boleh melebihi {}", max),
+            Message::PublishedInFuture => "published_date tidak boleh berada di masa depan".to_string(),
+            Message::LimitOutOfRange { max } => format!("limit harus di antara 1 dan {}", max),
+            Message::UnknownInclude { name } =>
+                format!("include {:?} tidak dikenal, seharusnya reviews atau tags", name),
+            Message::UnknownSort { name, fields } =>
+                format!(
+                    "kolom sort {:?} tidak dikenal, seharusnya salah satu dari {} (awali dengan - untuk urutan menurun)",
+                    name,
+                    fields
+                ),
+            Message::EmptyQuery => "q tidak boleh kosong".to_string(),
+            Message::NegativeOffset => "offset tidak boleh negatif".to_string(),
+            Message::DeltaOutOfRange { max } => format!("delta harus di antara -{0} dan {0}", max),
+            Message::AdjustConflict { id, delta, stock, max } =>
+                format!(
+                    "tidak dapat mengubah stok buku {} sebesar {}: stok saat ini {} dan harus tetap di antara 0 dan {}",
+                    id,
+                    delta,
+                    stock,
+                    max
+                ),
+            Message::QuantityTooLow => "quantity minimal 1".to_string(),
+            Message::ReserveConflict { quantity, id, available } =>
+                format!("tidak dapat memesan {} eksemplar buku {}: stok hanya {}", quantity, id, available),
+            Message::CheckoutSize { max } => format!("checkout harus berisi 1 sampai {} item", max),
+            Message::CheckoutQuantity { item } => format!("item checkout {}: quantity minimal 1", item),
+            Message::CheckoutNotFound { item, id } => format!("item checkout {}: buku {} tidak ditemukan", item, id),
+            Message::CheckoutConflict { item, quantity, id, available } =>
+                format!(
+                    "item checkout {}: tidak dapat mengambil {} eksemplar buku {}: stok hanya {}",
+                    item,
+                    quantity,
+                    id,
+                    available
+                ),
+            Message::CoverType => "sampul harus berupa gambar PNG, JPEG, GIF, atau WebP".to_string(),
+            Message::MissingField { name } => format!("field multipart `{}` tidak ada", name),
+            Message::CoverTooLarge { max } => format!("ukuran sampul maksimal {} byte", max),
+            Message::ReadOnly => "layanan sedang dalam mode baca saja; penulisan dinonaktifkan sementara".to_string(),
+            Message::Internal => "terjadi kesalahan internal pada server".to_string(),
+            Message::Rejection(_) => {
+                return None;
+            }
+        };
+        Some(text)
+    }
+
+    // The message in `language`, or `None` when it only exists in English
+    pub fn translate(&self, language: Language) -> Option<String> {
+        match language {
+            Language::English => Some(self.english()),
+            Language::Indonesian => self.indonesian(),
+        }
+    }
+}
+
+// English, as used in logs and in responses that are not localized
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.english())
+    }
+}
+
+// Middleware re-rendering `ApiError` messages in the language asked for by `Accept-Language`
+pub async fn localize(request: Request, next: Next) -> Response {
+    let language = Language::negotiate(request.headers().get(ACCEPT_LANGUAGE));
+    let mut response = next.run(request).await;
+
+    let Some(RenderedError { code, message }) = response.extensions().get::<RenderedError>().cloned() else {
+        return response;
+    };
+
+    let language = match message.translate(language) {
+        Some(translated) if language != Language::English => {
+            *response.body_mut() = Body::from(error::body(code, &translated).0.to_string());
+            response.headers_mut().remove(CONTENT_LENGTH);
+            language
+        }
+        _ => Language::English,
+    };
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_LANGUAGE, HeaderValue::from_static(language.tag()));
+    headers.append(VARY, HeaderValue::from_static("accept-language"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn negotiate(header: &str) -> Language {
+        Language::negotiate(Some(&HeaderValue::from_str(header).unwrap()))
+    }
+
+    #[test]
+    fn negotiate_picks_highest_weight() {
+        assert!(negotiate("en;q=0.5, id;q=0.8") == Language::Indonesian);
+        assert!(negotiate("id;q=0.3, en") == Language::English);
+        assert!(negotiate("fr, id;q=0.1") == Language::Indonesian);
+    }
+
+    #[test]
+    fn negotiate_matches_primary_subtag() {
+        assert!(negotiate("id-ID") == Language::Indonesian);
+        assert!(negotiate("ID-id;q=0.9, en;q=0.2") == Language::Indonesian);
+    }
+
+    #[test]
+    fn negotiate_ignores_zero_weight_and_unsupported() {
+        assert!(negotiate("id;q=0") == Language::English);
+        assert!(negotiate("id;q=0, en;q=0") == Language::English);
+        assert!(negotiate("fr, de;q=0.5") == Language::English);
+        assert!(negotiate("id;q=abc") == Language::English);
+        assert!(Language::negotiate(None) == Language::English);
+    }
+
+    #[test]
+    fn translate_places_values() {
+        let message = Message::BookNotFound { id: 7 };
+        assert_eq!(message.translate(Language::Indonesian).as_deref(), Some("buku 7 tidak ditemukan"));
+        assert_eq!(message.translate(Language::English).as_deref(), Some("book 7 not found"));
+        assert_eq!(
+            Message::ReserveConflict { quantity: 3, id: 9, available: 1 }.translate(Language::Indonesian).as_deref(),
+            Some("tidak dapat memesan 3 eksemplar buku 9: stok hanya 1")
+        );
+    }
+
+    #[test]
+    fn rejections_stay_in_english() {
+        let message = Message::Rejection("Failed to parse the request body as JSON".to_string());
+        assert_eq!(message.translate(Language::Indonesian), None);
+        assert_eq!(message.to_string(), "Failed to parse the request body as JSON");
+    }
+}
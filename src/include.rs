@@ -0,0 +1,44 @@
+use std::str::FromStr;
+use serde::Serialize;
+
+use crate::{ Book, Review, i18n::Message };
+
+// Related collections requested with `?include=reviews,tags`
+#[derive(Clone, Copy, Default)]
+pub struct Include {
+    pub reviews: bool,
+    pub tags: bool,
+}
+
+impl FromStr for Include {
+    type Err = Message;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut include = Include::default();
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "reviews" => {
+                    include.reviews = true;
+                }
+                "tags" => {
+                    include.tags = true;
+                }
+                _ => {
+                    return Err(Message::UnknownInclude { name: name.to_string() });
+                }
+            }
+        }
+        Ok(include)
+    }
+}
+
+// A book with the related collections that were asked for embedded next to its fields
+#[derive(Serialize)]
+pub struct BookWithRelations {
+    #[serde(flatten)]
+    pub book: Book,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reviews: Option<Vec<Review>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
@@ -0,0 +1,124 @@
+use axum::Json;
+use serde::{ Deserialize, Serialize };
+
+use crate::{ error::{ ApiError, ApiQuery }, i18n::Message };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsbnType {
+    Isbn10,
+    Isbn13,
+}
+
+// Strip separators and an `ISBN` label, then check the length and check digit.
+// Returns the bare digits (with an uppercase `X` check digit for ISBN-10).
+pub fn normalize(raw: &str) -> Result<(String, IsbnType), Message> {
+    let compact: String = raw
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let digits = ["ISBN13:", "ISBN10:", "ISBN:", "ISBN"]
+        .iter()
+        .find_map(|label| compact.strip_prefix(label))
+        .unwrap_or(&compact);
+
+    match digits.len() {
+        10 => {
+            let valid_chars = digits
+                .char_indices()
+                .all(|(i, c)| c.is_ascii_digit() || (i == 9 && c == 'X'));
+            if !valid_chars {
+                return Err(Message::IsbnCharacters);
+            }
+            let sum: u32 = digits
+                .chars()
+                .zip((1..=10).rev())
+                .map(|(c, weight)| c.to_digit(10).unwrap_or(10) * weight)
+                .sum();
+            if !sum.is_multiple_of(11) {
+                return Err(Message::IsbnCheckDigit);
+            }
+            Ok((digits.to_string(), IsbnType::Isbn10))
+        }
+        13 => {
+            if !digits.chars().all(|c| c.is_ascii_digit()) {
+                return Err(Message::IsbnCharacters);
+            }
+            let sum: u32 = digits
+                .chars()
+                .zip([1, 3].into_iter().cycle())
+                .map(|(c, weight)| c.to_digit(10).unwrap_or(0) * weight)
+                .sum();
+            if !sum.is_multiple_of(10) {
+                return Err(Message::IsbnCheckDigit);
+            }
+            Ok((digits.to_string(), IsbnType::Isbn13))
+        }
+        _ => Err(Message::IsbnLength),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ValidateParams {
+    isbn: String,
+}
+
+#[derive(Serialize)]
+pub struct Validation {
+    valid: bool,
+    normalized: String,
+    #[serde(rename = "type")]
+    isbn_type: IsbnType,
+}
+
+// Check an ISBN the same way book payloads are checked, without storing anything
+pub async fn validate(ApiQuery(params): ApiQuery<ValidateParams>) -> Result<Json<Validation>, ApiError> {
+    let (normalized, isbn_type) = normalize(&params.isbn).map_err(ApiError::BadRequest)?;
+    Ok(Json(Validation { valid: true, normalized, isbn_type }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_isbn10() {
+        assert_eq!(normalize("0-306-40615-2"), Ok(("0306406152".to_string(), IsbnType::Isbn10)));
+        assert_eq!(normalize("ISBN 0 306 40615 2"), Ok(("0306406152".to_string(), IsbnType::Isbn10)));
+    }
+
+    #[test]
+    fn accepts_isbn10_with_x_check_digit() {
+        assert_eq!(normalize("0-8044-2957-X"), Ok(("080442957X".to_string(), IsbnType::Isbn10)));
+        assert_eq!(normalize("080442957x"), Ok(("080442957X".to_string(), IsbnType::Isbn10)));
+    }
+
+    #[test]
+    fn accepts_isbn13() {
+        assert_eq!(normalize("978-0-306-40615-7"), Ok(("9780306406157".to_string(), IsbnType::Isbn13)));
+        assert_eq!(normalize("ISBN-13: 978-0-306-40615-7"), Ok(("9780306406157".to_string(), IsbnType::Isbn13)));
+        assert_eq!(normalize("isbn-10: 0-306-40615-2"), Ok(("0306406152".to_string(), IsbnType::Isbn10)));
+    }
+
+    #[test]
+    fn rejects_bad_check_digits() {
+        assert_eq!(normalize("0306406153"), Err(Message::IsbnCheckDigit));
+        assert_eq!(normalize("9780306406158"), Err(Message::IsbnCheckDigit));
+    }
+
+    #[test]
+    fn rejects_misplaced_x_and_letters() {
+        assert_eq!(normalize("X306406152"), Err(Message::IsbnCharacters));
+        assert_eq!(normalize("978030640615X"), Err(Message::IsbnCharacters));
+        assert_eq!(normalize("97803064A6157"), Err(Message::IsbnCharacters));
+    }
+
+    #[test]
+    fn rejects_wrong_lengths() {
+        assert_eq!(normalize(""), Err(Message::IsbnLength));
+        assert_eq!(normalize("12345"), Err(Message::IsbnLength));
+        assert_eq!(normalize("030640615"), Err(Message::IsbnLength));
+        assert_eq!(normalize("97803064061570"), Err(Message::IsbnLength));
+    }
+}
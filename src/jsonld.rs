@@ -0,0 +1,32 @@
+use axum::{ http::header, response::{ IntoResponse, Response } };
+use serde_json::{ Map, Value, json };
+
+use crate::Book;
+
+pub const CONTENT_TYPE: &str = "application/ld+json";
+
+// Render a book as a schema.org `Book` in JSON-LD, identified by its public URL
+pub fn response(book: &Book, public_base_url: &str) -> Response {
+    let url = format!("{}/books/{}", public_base_url, book.id);
+    let mut document = Map::new();
+    document.insert("@context".to_string(), json!("https://schema.org"));
+    document.insert("@type".to_string(), json!("Book"));
+    document.insert("@id".to_string(), json!(url));
+    document.insert("url".to_string(), json!(url));
+    document.insert("name".to_string(), json!(book.title));
+    if !book.authors.is_empty() {
+        let authors: Vec<Value> = book.authors
+            .iter()
+            .map(|name| json!({ "@type": "Person", "name": name }))
+            .collect();
+        document.insert("author".to_string(), Value::Array(authors));
+    }
+    if let Some(date) = book.published_date {
+        document.insert("datePublished".to_string(), json!(date.to_string()));
+    }
+    if let Some(isbn) = &book.isbn {
+        document.insert("isbn".to_string(), json!(isbn));
+    }
+
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], Value::Object(document).to_string()).into_response()
+}
@@ -1,152 +1,792 @@
-use std::env;
+use std::{ env, sync::Arc, time::Duration };
 use axum::{
     Json,
     Router,
-    extract::{ Path, State },
-    http::StatusCode,
-    routing::{ get },
+    extract::{ DefaultBodyLimit, State },
+    http::{ Method, StatusCode },
+    response::{ IntoResponse, Response },
+    middleware,
+    routing::{ any, get, patch, post },
 };
-use chrono::{ NaiveDate, NaiveDateTime };
+use chrono::{ NaiveDate, NaiveDateTime, Utc };
+use metrics_exporter_prometheus::PrometheusHandle;
+use schemars::JsonSchema;
 use serde::{ Deserialize, Serialize };
-use sqlx::{ PgPool, postgres::PgPoolOptions, prelude::FromRow };
+use sqlx::{ PgPool, prelude::FromRow };
+use tower_http::compression::CompressionLayer;
+
+mod banner;
+mod bibtex;
+mod cache;
+mod config;
+mod cors;
+mod covers;
+mod date_format;
+mod db;
+mod dedup;
+mod error;
+mod expiry;
+mod export;
+mod features;
+mod feed;
+mod health;
+mod i18n;
+mod include;
+mod isbn;
+mod jsonld;
+mod metrics;
+mod notify;
+mod pagination;
+mod panic;
+mod qr;
+mod read_only;
+mod repository;
+mod schema;
+mod shutdown;
+mod timing;
+mod sort;
+
+use cache::BookCache;
+use config::{ env_flag, env_optional };
+use date_format::{ DateFormat, DateFormatted };
+use dedup::{ CreateDedup, Created };
+use error::{ ApiError, ApiJson, ApiQuery, BookId, BookIdOrBib };
+use features::Features;
+use i18n::Message;
+use include::{ BookWithRelations, Include };
+use notify::BookChange;
+use pagination::{ Enveloped, Page, Paginated };
+use read_only::Writable;
+use repository::{ CheckoutOutcome, ReserveOutcome };
+use sort::Sort;
+
+// Matches the books_stock_max CHECK constraint
+const DEFAULT_MAX_STOCK: i32 = 100_000;
+const DEFAULT_SIMILAR_LIMIT: i64 = 5;
+const MAX_SIMILAR_LIMIT: i64 = 50;
+const MAX_CHECKOUT_ITEMS: usize = 100;
+const AUTOCOMPLETE_LIMIT: i64 = 10;
+const MAX_AUTHORS: usize = 50;
+// Matches the generated books.author column, which holds the first of `authors`
+const MAX_AUTHOR_LENGTH: usize = 255;
+// Matches the books.title column
+const MAX_TITLE_LENGTH: usize = 255;
+const MAX_TAGS: usize = 100;
+// Matches the tags.name column
+const MAX_TAG_LENGTH: usize = 64;
+const DEFAULT_EXPIRY_INTERVAL_SECS: u64 = 300;
+// Set on a create response that returned an earlier book instead of inserting
+const DEDUPLICATED_HEADER: &str = "x-deduplicated";
+
+#[derive(Clone)]
+struct AppState {
+    pool: PgPool,
+    metrics: PrometheusHandle,
+    // Order used by `list_book` when no `?sort=` is given
+    default_sort: Sort,
+    book_cache: Arc<BookCache>,
+    create_dedup: Arc<CreateDedup>,
+    // Externally reachable origin used in generated links, without a trailing slash
+    public_base_url: Arc<str>,
+    // Number of books in the new arrivals feed
+    feed_size: i64,
+    // Upper bound on a book's stock
+    max_stock: i32,
+    // Most rows any list query returns, whatever the request asks for
+    result_cap: i64,
+    // Whether list and detail responses are wrapped in `{"data": ...}` when `?envelope=` is not given
+    envelope: bool,
+    // Whether handlers taking `Writable` reject requests with 503
+    read_only: bool,
+}
+
+impl AppState {
+    // Drop what the cache and create dedup remember about a book the API just changed
+    fn book_changed(&self, id: i32) {
+        self.book_cache.invalidate(BookChange::Book(id));
+        self.create_dedup.forget(id);
+    }
+}
 
 #[derive(Deserialize)]
+struct ListParams {
+    sort: Option<String>,
+    // Only books with this author among their authors, ignoring case
+    author: Option<String>,
+    #[serde(default)]
+    date_format: DateFormat,
+    envelope: Option<bool>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
 struct Bookpayload {
     title: String,
+    // Single-author shorthand kept for older clients; must match `authors[0]` if both are given
     author: Option<String>,
+    authors: Option<Vec<String>>,
+    // ISBN-10 or ISBN-13, hyphens and spaces allowed; stored normalized
+    isbn: Option<String>,
     stock: i32,
     published_date: Option<NaiveDate>,
 }
 
-#[derive(Serialize, FromRow)]
+impl Bookpayload {
+    // Check the rules a well-formed payload must still satisfy
+    fn validate(&self, max_stock: i32) -> Result<(), ApiError> {
+        if self.title.trim().is_empty() {
+            return Err(ApiError::Unprocessable(Message::TitleEmpty));
+        }
+        if self.title.trim().chars().count() > MAX_TITLE_LENGTH {
+            return Err(ApiError::Unprocessable(Message::TitleTooLong { max: MAX_TITLE_LENGTH }));
+        }
+        let too_long = |author: &String| author.trim().chars().count() > MAX_AUTHOR_LENGTH;
+        if self.author.iter().chain(self.authors.iter().flatten()).any(too_long) {
+            return Err(ApiError::Unprocessable(Message::AuthorTooLong { max: MAX_AUTHOR_LENGTH }));
+        }
+        if let Some(authors) = &self.authors {
+            if authors.len() > MAX_AUTHORS {
+                return Err(ApiError::Unprocessable(Message::TooManyAuthors { max: MAX_AUTHORS }));
+            }
+            if authors.iter().any(|author| author.trim().is_empty()) {
+                return Err(ApiError::Unprocessable(Message::EmptyAuthor));
+            }
+            if let Some(author) = &self.author {
+                if authors.first().map(|first| first.trim()) != Some(author.trim()) {
+                    return Err(ApiError::Unprocessable(Message::AuthorMismatch));
+                }
+            }
+        }
+        self.isbn().map_err(ApiError::Unprocessable)?;
+        validate_stock(self.stock, max_stock)?;
+        if self.published_date.is_some_and(|date| date > Utc::now().date_naive()) {
+            return Err(ApiError::Unprocessable(Message::PublishedInFuture));
+        }
+        Ok(())
+    }
+
+    // Authors to store, trimmed: `authors` when given, otherwise the single `author`
+    fn authors(&self) -> Vec<String> {
+        match (&self.authors, &self.author) {
+            (Some(authors), _) => authors.iter().map(|author| author.trim().to_string()).collect(),
+            (None, Some(author)) if !author.trim().is_empty() => vec![author.trim().to_string()],
+            (None, _) => Vec::new(),
+        }
+    }
+
+    // The ISBN in its stored form, `None` when absent or blank
+    fn isbn(&self) -> Result<Option<String>, Message> {
+        match self.isbn.as_deref().map(str::trim).filter(|isbn| !isbn.is_empty()) {
+            Some(isbn) => isbn::normalize(isbn).map(|(normalized, _)| Some(normalized)),
+            None => Ok(None),
+        }
+    }
+}
+
+// Shared by full edits and `PATCH /books/{id}/stock`
+fn validate_stock(stock: i32, max_stock: i32) -> Result<(), ApiError> {
+    if stock < 0 {
+        return Err(ApiError::Unprocessable(Message::StockNegative));
+    }
+    if stock > max_stock {
+        return Err(ApiError::Unprocessable(Message::StockTooHigh { max: max_stock }));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Serialize, FromRow, JsonSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
 struct Book {
     id: i32,
     title: String,
+    // First of `authors`, for clients that only know about a single author
     author: Option<String>,
+    authors: Vec<String>,
+    isbn: Option<String>,
     published_date: Option<NaiveDate>,
     stock: i32,
     created_at: NaiveDateTime,
 }
 
+#[derive(Deserialize)]
+struct IncompleteParams {
+    // `author`, `published_date` or `any` (the default)
+    missing: Option<Missing>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+// Metadata a book may be missing
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Missing {
+    Author,
+    PublishedDate,
+    #[default]
+    Any,
+}
+
+#[derive(Deserialize)]
+struct GetParams {
+    // Skip the book cache and read straight from the database
+    #[serde(default)]
+    nocache: bool,
+    // Comma-separated related collections to embed: `reviews`, `tags`
+    include: Option<String>,
+    #[serde(default)]
+    date_format: DateFormat,
+    envelope: Option<bool>,
+    #[serde(default)]
+    format: Format,
+}
+
+// Representation of a single book, chosen with `?format=`
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    #[default]
+    Json,
+    // schema.org `Book` as JSON-LD
+    Jsonld,
+}
+
+#[derive(Deserialize)]
+struct AutocompleteParams {
+    // Title prefix typed so far
+    q: String,
+}
+
+#[derive(Deserialize)]
+struct SimilarParams {
+    limit: Option<i64>,
+    envelope: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct DeleteParams {
+    // Respond with the deleted book instead of an empty 204
+    #[serde(rename = "return", default)]
+    return_deleted: bool,
+}
+
+#[derive(Deserialize)]
+struct StockAdjustment {
+    delta: i32,
+}
+
+#[derive(Deserialize)]
+struct StockUpdate {
+    stock: i32,
+}
+
+#[derive(Deserialize)]
+struct ReservationRequest {
+    quantity: i32,
+}
+
+// Books that look like the same title by the same authors
+#[derive(Serialize)]
+struct DuplicateGroup {
+    // Title and first author as written on the oldest book of the group
+    title: String,
+    author: Option<String>,
+    books: Vec<DuplicateEntry>,
+}
+
+#[derive(Serialize)]
+struct DuplicateEntry {
+    id: i32,
+    stock: i32,
+}
+
+// One line of a checkout manifest
+#[derive(Deserialize)]
+struct CheckoutItem {
+    id: i32,
+    quantity: i32,
+}
+
+#[derive(Serialize, FromRow)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+struct Reservation {
+    id: i32,
+    book_id: i32,
+    quantity: i32,
+    status: String,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, FromRow)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+struct Review {
+    id: i32,
+    rating: i16,
+    comment: Option<String>,
+    created_at: NaiveDateTime,
+}
+
 #[tokio::main]
 async fn main() {
-    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = PgPoolOptions::new().connect(&db_url).await.expect("Failed to connect to DB");
-    sqlx::migrate!().run(&pool).await.expect("Migration failed");
+    tracing_subscriber
+        ::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into())
+        )
+        .init();
 
-    let app = Router::new()
-        .route("/", get(home))
-        .route("/books", get(list_book).post(create_book))
-        .route("/books/{id}", get(get_book).put(update_book).delete(delete_book))
-        .with_state(pool);
+    let features = Features::from_env();
+    let metrics = metrics::install_recorder();
+    metrics::set_slow_query_ms(env_optional("SLOW_QUERY_MS").unwrap_or(metrics::DEFAULT_SLOW_QUERY_MS));
+    let default_sort = env_optional::<Sort>("DEFAULT_SORT").unwrap_or_default();
+    let book_cache = Arc::new(BookCache::new(env_optional("BOOK_CACHE_CAPACITY").unwrap_or(0)));
+    let create_dedup = Arc::new(
+        CreateDedup::new(Duration::from_secs(env_optional("CREATE_DEDUP_WINDOW_SECS").unwrap_or(0)))
+    );
+    let public_base_url: Arc<str> = env
+        ::var("PUBLIC_BASE_URL")
+        .unwrap_or_else(|_| "http://localhost:8000".to_string())
+        .trim_end_matches('/')
+        .into();
+    // Never below a full page, so paginated and fixed-size lists are never cut short
+    let result_cap = env_optional("HARD_RESULT_CAP").unwrap_or(pagination::DEFAULT_RESULT_CAP);
+    if !(pagination::MAX_LIMIT..=pagination::MAX_RESULT_CAP).contains(&result_cap) {
+        panic!(
+            "HARD_RESULT_CAP must be between {} and {}, got {}",
+            pagination::MAX_LIMIT,
+            pagination::MAX_RESULT_CAP,
+            result_cap
+        );
+    }
+    let feed_size = env_optional("FEED_SIZE").unwrap_or(20);
+    if !(1..=result_cap).contains(&feed_size) {
+        panic!("FEED_SIZE must be between 1 and HARD_RESULT_CAP ({}), got {}", result_cap, feed_size);
+    }
+    let max_stock = env_optional("MAX_STOCK").unwrap_or(DEFAULT_MAX_STOCK);
+    if !(0..=DEFAULT_MAX_STOCK).contains(&max_stock) {
+        panic!("MAX_STOCK must be between 0 and {} (the books_stock_max constraint), got {}", DEFAULT_MAX_STOCK, max_stock);
+    }
+    let shutdown_timeout = Duration::from_secs(env_optional("SHUTDOWN_TIMEOUT_SECS").unwrap_or(30));
+    let in_flight = shutdown::InFlight::default();
+    let (stop, stopping) = shutdown::stopping();
+    let read_only = env_flag("READ_ONLY", false);
+    let envelope = env_flag("RESPONSE_ENVELOPE", false);
+
+    let db_options = db::connect_options();
+    let schema = db::schema();
+    let pool = db::connect(db_options.clone(), &schema).await;
+    // The database may itself be read-only during maintenance, so run no DDL against it
+    if read_only {
+        db::check_migrated(&pool).await;
+    } else {
+        db::migrate(&pool, &schema).await;
+    }
+    if env_flag("STARTUP_SELF_TEST", false) {
+        // The probe insert is a write too, even though it is rolled back
+        if read_only {
+            tracing::warn!("READ_ONLY is set: the startup self-test is skipped");
+        } else {
+            if let Err(e) = repository::self_test(&pool).await {
+                panic!("Startup self-test failed: could not insert and read back a book (check table grants): {}", e);
+            }
+            tracing::info!("Startup self-test passed");
+        }
+    }
+    // Opt-in: without a TTL reservations are kept until acted on
+    let expiry = env_optional("RESERVATION_TTL_SECS").and_then(|ttl| {
+        // Expiring writes to reservations and books, which read-only mode exists to stop
+        if read_only {
+            tracing::warn!("READ_ONLY is set: reservation expiry is skipped until the server restarts without it");
+            return None;
+        }
+        let every = env_optional("RESERVATION_EXPIRY_INTERVAL_SECS").unwrap_or(DEFAULT_EXPIRY_INTERVAL_SECS);
+        if every == 0 {
+            panic!("RESERVATION_EXPIRY_INTERVAL_SECS must be at least 1");
+        }
+        Some(expiry::spawn(pool.clone(), Duration::from_secs(ttl), Duration::from_secs(every), max_stock, stopping.clone()))
+    });
+    // Also catches writes made outside the API; without a cache there is nothing to invalidate
+    if book_cache.is_enabled() {
+        let listener_cache = book_cache.clone();
+        notify::spawn_listener(pool.clone(), move |change| listener_cache.invalidate(change));
+    }
+
+    let state = AppState {
+        pool,
+        metrics,
+        default_sort,
+        book_cache,
+        create_dedup,
+        public_base_url,
+        feed_size,
+        max_stock,
+        result_cap,
+        envelope,
+        read_only,
+    };
+    if read_only {
+        tracing::warn!("READ_ONLY is set: POST, PUT, PATCH and DELETE requests are rejected with 503");
+    }
+    let app = routes(features)
+        .layer(panic::layer())
+        .layer(middleware::from_fn(i18n::localize))
+        .layer(middleware::from_fn_with_state(in_flight.clone(), shutdown::track))
+        .layer(cors::layer())
+        // Honors Accept-Encoding; mainly pays off for large responses such as the CSV export
+        .layer(CompressionLayer::new())
+        // Outermost, so CORS preflight answers and compressed responses are timed too
+        .layer(middleware::from_fn(timing::response_time))
+        .with_state(state.clone());
 
     let listener = tokio::net::TcpListener
         ::bind("0.0.0.0:8000").await
         .expect("Failed to bind to address");
 
-    println!("Server running on http://0.0.0.0:8000");
-    axum::serve(listener, app).await.unwrap();
+    banner::log(listener.local_addr().expect("Listener has no local address"), &db_options, features, &state);
+    shutdown::serve(listener, app, in_flight, shutdown_timeout, stop).await;
+    if let Some(expiry) = expiry {
+        let _ = expiry.await;
+    }
+}
+
+// All routes, leaving out the optional ones that are disabled
+fn routes(features: Features) -> Router<AppState> {
+    let mut router = Router::new()
+        .route("/", get(home))
+        .route("/health/detailed", get(health::detailed))
+        .route("/isbn/validate", get(isbn::validate))
+        .route("/books", get(list_book).post(create_book))
+        .route("/books/incomplete", get(incomplete_books))
+        .route("/books/feed.xml", get(feed::new_arrivals))
+        .route("/books/duplicates", get(duplicate_books))
+        .route("/books/export.csv", get(export::books_csv))
+        .route("/books/checkout-batch", post(checkout_batch))
+        .route("/books/{id}", get(get_book).put(update_book).delete(delete_book))
+        .route("/books/{id}/similar", get(similar_books))
+        .route("/books/{id}/stock", patch(set_stock))
+        .route("/books/{id}/adjust-stock", post(adjust_stock))
+        .route("/books/{id}/reservations", post(reserve_book))
+        .route("/books/{id}/tags/batch", post(set_book_tags))
+        .route("/books/{id}/qr", get(qr::book_qr));
+
+    if features.metrics {
+        router = router.route("/metrics", get(metrics::render));
+    }
+    if features.schema {
+        router = router
+            .route("/schema/book", get(schema::book))
+            .route("/schema/book-payload", get(schema::book_payload));
+    }
+    // Without this the path would fall through to `/books/{id}` and be rejected as a bad ID
+    router = if features.search {
+        router.route("/books/autocomplete", get(autocomplete))
+    } else {
+        router.route("/books/autocomplete", any(route_not_found))
+    };
+    if features.covers {
+        router = router.route(
+            "/books/{id}/cover",
+            post(covers::upload_cover)
+                .get(covers::get_cover)
+                // Leave room for the multipart framing around the image itself
+                .layer(DefaultBodyLimit::max(covers::MAX_COVER_BYTES + 64 * 1024))
+        );
+    }
+
+    router.fallback(route_not_found).method_not_allowed_fallback(method_not_allowed)
+}
+
+// Unknown paths, including optional routes that are disabled, get the usual error body
+async fn route_not_found() -> ApiError {
+    ApiError::NotFound(Message::RouteNotFound)
+}
+
+// Known paths hit with an unsupported method; the `Allow` header lists the supported ones
+async fn method_not_allowed(method: Method) -> ApiError {
+    ApiError::MethodNotAllowed(Message::MethodNotAllowed { method: method.to_string() })
 }
 
 async fn home() -> &'static str {
     "Welcome to Bookshelf API"
 }
 
-// Get all books
-async fn list_book(State(pool): State<PgPool>) -> Result<Json<Vec<Book>>, StatusCode> {
-    sqlx::query_as::<_, Book>("SELECT * FROM books")
-        .fetch_all(&pool).await
-        .map(Json)
-        .map_err(|e| {
-            eprintln!("List books error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+// Get all books, up to the hard result cap
+async fn list_book(
+    State(state): State<AppState>,
+    ApiQuery(params): ApiQuery<ListParams>
+) -> Result<Response, ApiError> {
+    let sort = match params.sort {
+        Some(sort) => sort.parse().map_err(ApiError::BadRequest)?,
+        None => state.default_sort,
+    };
+
+    let author = params.author.as_deref().map(str::trim).filter(|author| !author.is_empty());
+    let books = repository::list(&state.pool, sort, author, state.result_cap + 1).await?;
+    let (books, truncated) = pagination::truncate(books, state.result_cap);
+    let envelope = params.envelope.unwrap_or(state.envelope);
+    let body = Enveloped::capped(DateFormatted(books, params.date_format), envelope, truncated);
+    Ok(pagination::mark_truncated(Json(body).into_response(), truncated))
 }
 
-// Create a new book
+// Create a new book, or return the one an identical recent request created
 async fn create_book(
-    State(pool): State<PgPool>,
-    Json(payload): Json<Bookpayload>
-) -> Result<(StatusCode, Json<Book>), StatusCode> {
-    // Validate title
-    if payload.title.trim().is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    sqlx::query_as::<_, Book>(
-        "INSERT INTO books (title, author, published_date, stock) VALUES ($1, $2, $3, $4) RETURNING *"
-    )
-        .bind(payload.title.trim())
-        .bind(payload.author.as_ref().map(|a| a.trim()))
-        .bind(payload.published_date)
-        .bind(payload.stock)
-        .fetch_one(&pool).await
-        .map(|book| (StatusCode::CREATED, Json(book)))
-        .map_err(|e| {
-            eprintln!("Create book error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
-}
-
-// Get book by ID
+    State(state): State<AppState>,
+    _: Writable,
+    ApiJson(payload): ApiJson<Bookpayload>
+) -> Result<Response, ApiError> {
+    payload.validate(state.max_stock)?;
+
+    let created = state.create_dedup.create(&payload, repository::create(&state.pool, &payload)).await?;
+    Ok(match created {
+        Created::New(book) => (StatusCode::CREATED, Json(book)).into_response(),
+        Created::Duplicate(book) => ([(DEDUPLICATED_HEADER, "true")], Json(book)).into_response(),
+    })
+}
+
+// Get books without an author or published date
+async fn incomplete_books(
+    State(state): State<AppState>,
+    ApiQuery(params): ApiQuery<IncompleteParams>
+) -> Result<Json<Paginated<Book>>, ApiError> {
+    let page = Page::new(params.limit, params.offset)?;
+    let (books, total) = repository::incomplete(&state.pool, params.missing.unwrap_or_default(), page).await?;
+    Ok(Json(Paginated::new(books, page, total)))
+}
+
+// Get book by ID, or as a BibTeX entry when requested as `/books/{id}.bib`
 async fn get_book(
-    State(pool): State<PgPool>,
-    Path(id): Path<i32>
-) -> Result<Json<Book>, StatusCode> {
-    sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = $1")
-        .bind(id)
-        .fetch_one(&pool).await
-        .map(Json)
-        .map_err(|e| {
-            eprintln!("Get book error: {}", e);
-            StatusCode::NOT_FOUND
-        })
+    State(state): State<AppState>,
+    BookIdOrBib { id, bibtex: as_bibtex }: BookIdOrBib,
+    ApiQuery(params): ApiQuery<GetParams>
+) -> Result<Response, ApiError> {
+    let include = match &params.include {
+        Some(include) => include.parse::<Include>().map_err(ApiError::BadRequest)?,
+        None => Include::default(),
+    };
+
+    let cached = if params.nocache { None } else { state.book_cache.get(id) };
+    let book = match cached {
+        Some(book) => book,
+        None => {
+            let generation = state.book_cache.generation();
+            let book = repository::find_by_id(&state.pool, id).await?.ok_or_else(|| book_not_found(id))?;
+            state.book_cache.insert(&book, generation);
+            book
+        }
+    };
+
+    if as_bibtex {
+        return Ok(bibtex::response(&book));
+    }
+    if params.format == Format::Jsonld {
+        return Ok(jsonld::response(&book, &state.public_base_url));
+    }
+    let envelope = params.envelope.unwrap_or(state.envelope);
+    if !include.reviews && !include.tags {
+        return Ok(Json(Enveloped::new(DateFormatted(book, params.date_format), envelope)).into_response());
+    }
+
+    let reviews = if include.reviews { Some(repository::reviews_for(&state.pool, id).await?) } else { None };
+    let tags = if include.tags { Some(repository::tags_for(&state.pool, id).await?) } else { None };
+    let book = DateFormatted(BookWithRelations { book, reviews, tags }, params.date_format);
+    Ok(Json(Enveloped::new(book, envelope)).into_response())
+}
+
+// Groups of books sharing a title and authors once case and spacing are ignored
+async fn duplicate_books(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let groups = repository::duplicates(&state.pool, state.result_cap + 1).await?;
+    let (groups, truncated) = pagination::truncate(groups, state.result_cap);
+    Ok(pagination::mark_truncated(Json(Enveloped::capped(groups, false, truncated)).into_response(), truncated))
+}
+
+// Suggest titles starting with what has been typed so far
+async fn autocomplete(
+    State(state): State<AppState>,
+    ApiQuery(params): ApiQuery<AutocompleteParams>
+) -> Result<Json<Vec<String>>, ApiError> {
+    let prefix = params.q.trim();
+    if prefix.is_empty() {
+        return Err(ApiError::BadRequest(Message::EmptyQuery));
+    }
+
+    Ok(Json(repository::title_completions(&state.pool, prefix, AUTOCOMPLETE_LIMIT).await?))
+}
+
+// Get books similar to the given one
+async fn similar_books(
+    State(state): State<AppState>,
+    BookId(id): BookId,
+    ApiQuery(params): ApiQuery<SimilarParams>
+) -> Result<Json<Enveloped<Vec<Book>>>, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_SIMILAR_LIMIT);
+    if !(1..=MAX_SIMILAR_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(Message::LimitOutOfRange { max: MAX_SIMILAR_LIMIT }));
+    }
+
+    if repository::find_by_id(&state.pool, id).await?.is_none() {
+        return Err(book_not_found(id));
+    }
+
+    let books = repository::similar(&state.pool, id, limit).await?;
+    Ok(Json(Enveloped::new(books, params.envelope.unwrap_or(state.envelope))))
 }
 
 // Update a book
 async fn update_book(
-    State(pool): State<PgPool>,
-    Path(id): Path<i32>,
-    Json(payload): Json<Bookpayload>
-) -> Result<Json<Book>, StatusCode> {
-    // Validate title
-    if payload.title.trim().is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    sqlx::query_as::<_, Book>(
-        "UPDATE books SET title = $1, author = $2, published_date = $3, stock = $4 WHERE id = $5 RETURNING *"
-    )
-        .bind(payload.title.trim())
-        .bind(payload.author.as_ref().map(|a| a.trim()))
-        .bind(payload.published_date)
-        .bind(payload.stock)
-        .bind(id)
-        .fetch_one(&pool).await
-        .map(Json)
-        .map_err(|e| {
-            eprintln!("Update book error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
-}
-
-// Delete a book
+    State(state): State<AppState>,
+    _: Writable,
+    BookId(id): BookId,
+    ApiJson(payload): ApiJson<Bookpayload>
+) -> Result<Json<Book>, ApiError> {
+    payload.validate(state.max_stock)?;
+
+    let book = repository::update(&state.pool, id, &payload).await?.ok_or_else(|| book_not_found(id))?;
+    state.book_changed(id);
+    Ok(Json(book))
+}
+
+// Add a signed delta to a book's stock atomically
+async fn adjust_stock(
+    State(state): State<AppState>,
+    _: Writable,
+    BookId(id): BookId,
+    ApiJson(adjustment): ApiJson<StockAdjustment>
+) -> Result<Json<Book>, ApiError> {
+    if adjustment.delta.unsigned_abs() > state.max_stock.unsigned_abs() {
+        return Err(ApiError::Unprocessable(Message::DeltaOutOfRange { max: state.max_stock }));
+    }
+
+    let Some(book) = repository::adjust_stock(&state.pool, id, adjustment.delta, state.max_stock).await? else {
+        // Nothing was updated: either the book is missing or the stock would leave 0..=max_stock
+        return match repository::find_by_id(&state.pool, id).await? {
+            Some(book) =>
+                Err(
+                    ApiError::Conflict(Message::AdjustConflict {
+                        id,
+                        delta: adjustment.delta,
+                        stock: book.stock,
+                        max: state.max_stock,
+                    })
+                ),
+            None => Err(book_not_found(id)),
+        };
+    };
+
+    state.book_changed(id);
+    Ok(Json(book))
+}
+
+// Replace a book's stock without touching the rest of it
+async fn set_stock(
+    State(state): State<AppState>,
+    _: Writable,
+    BookId(id): BookId,
+    ApiJson(update): ApiJson<StockUpdate>
+) -> Result<Json<Book>, ApiError> {
+    validate_stock(update.stock, state.max_stock)?;
+
+    let book = repository::set_stock(&state.pool, id, update.stock).await?.ok_or_else(|| book_not_found(id))?;
+    state.book_changed(id);
+    Ok(Json(book))
+}
+
+// Reserve copies of a book, taking them out of stock
+async fn reserve_book(
+    State(state): State<AppState>,
+    _: Writable,
+    BookId(id): BookId,
+    ApiJson(request): ApiJson<ReservationRequest>
+) -> Result<(StatusCode, Json<Reservation>), ApiError> {
+    if request.quantity < 1 {
+        return Err(ApiError::Unprocessable(Message::QuantityTooLow));
+    }
+
+    match repository::reserve(&state.pool, id, request.quantity).await? {
+        ReserveOutcome::Reserved(reservation) => {
+            state.book_changed(id);
+            Ok((StatusCode::CREATED, Json(reservation)))
+        }
+        ReserveOutcome::NotFound => Err(book_not_found(id)),
+        ReserveOutcome::InsufficientStock { available } =>
+            Err(ApiError::Conflict(Message::ReserveConflict { quantity: request.quantity, id, available })),
+    }
+}
+
+// Take a whole manifest of books out of stock at once, all or nothing
+async fn checkout_batch(
+    State(state): State<AppState>,
+    _: Writable,
+    ApiJson(items): ApiJson<Vec<CheckoutItem>>
+) -> Result<Json<Vec<Book>>, ApiError> {
+    if items.is_empty() || items.len() > MAX_CHECKOUT_ITEMS {
+        return Err(ApiError::Unprocessable(Message::CheckoutSize { max: MAX_CHECKOUT_ITEMS }));
+    }
+    if let Some(index) = items.iter().position(|item| item.quantity < 1) {
+        return Err(ApiError::Unprocessable(Message::CheckoutQuantity { item: index }));
+    }
+
+    match repository::checkout(&state.pool, &items).await? {
+        CheckoutOutcome::Completed(books) => {
+            for book in &books {
+                state.book_changed(book.id);
+            }
+            Ok(Json(books))
+        }
+        CheckoutOutcome::NotFound { item, id } =>
+            Err(ApiError::NotFound(Message::CheckoutNotFound { item, id })),
+        CheckoutOutcome::InsufficientStock { item, id, available } =>
+            Err(
+                ApiError::Conflict(Message::CheckoutConflict { item, quantity: items[item].quantity, id, available })
+            ),
+    }
+}
+
+// Replace a book's tags with the given list, returning the resulting set
+async fn set_book_tags(
+    State(state): State<AppState>,
+    _: Writable,
+    BookId(id): BookId,
+    ApiJson(names): ApiJson<Vec<String>>
+) -> Result<Json<Vec<String>>, ApiError> {
+    if names.len() > MAX_TAGS {
+        return Err(ApiError::Unprocessable(Message::TooManyTags { max: MAX_TAGS }));
+    }
+    let mut tags: Vec<String> = Vec::with_capacity(names.len());
+    for name in &names {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH {
+            return Err(ApiError::Unprocessable(Message::TagLength { max: MAX_TAG_LENGTH }));
+        }
+        if !tags.iter().any(|tag| tag == name) {
+            tags.push(name.to_string());
+        }
+    }
+
+    let tags = repository::set_tags(&state.pool, id, &tags).await?.ok_or_else(|| book_not_found(id))?;
+    Ok(Json(tags))
+}
+
+// Delete a book, optionally returning it with `?return=true`
 async fn delete_book(
-    State(pool): State<PgPool>,
-    Path(id): Path<i32>
-) -> Result<StatusCode, StatusCode> {
-    let result = sqlx
-        ::query("DELETE FROM books WHERE id = $1")
-        .bind(id)
-        .execute(&pool).await
-        .map_err(|e| {
-            eprintln!("Delete book error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    if result.rows_affected() == 0 {
-        Err(StatusCode::NOT_FOUND)
+    State(state): State<AppState>,
+    _: Writable,
+    BookId(id): BookId,
+    ApiQuery(params): ApiQuery<DeleteParams>
+) -> Result<Response, ApiError> {
+    let response = if params.return_deleted {
+        repository
+            ::delete_returning(&state.pool, id).await?
+            .map(|book| Json(book).into_response())
+            .ok_or_else(|| book_not_found(id))?
+    } else if repository::delete(&state.pool, id).await? {
+        StatusCode::NO_CONTENT.into_response()
     } else {
-        Ok(StatusCode::NO_CONTENT)
-    }
+        return Err(book_not_found(id));
+    };
+
+    state.book_changed(id);
+    Ok(response)
+}
+
+fn book_not_found(id: i32) -> ApiError {
+    ApiError::NotFound(Message::BookNotFound { id })
 }
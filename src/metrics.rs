@@ -0,0 +1,55 @@
+use std::{ future::Future, sync::OnceLock, time::{ Duration, Instant } };
+use axum::extract::State;
+use metrics_exporter_prometheus::{ Matcher, PrometheusBuilder, PrometheusHandle };
+
+use crate::AppState;
+
+const QUERY_DURATION: &str = "db_query_duration_seconds";
+pub const DEFAULT_SLOW_QUERY_MS: u64 = 500;
+
+// Queries taking longer than this are logged; `None` turns the warning off
+static SLOW_QUERY: OnceLock<Option<Duration>> = OnceLock::new();
+
+// Buckets tuned for database round trips: 1ms up to 5s
+const QUERY_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+// Install the global Prometheus recorder used by the `metrics` facade
+pub fn install_recorder() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(QUERY_DURATION.to_string()), QUERY_DURATION_BUCKETS)
+        .expect("Invalid histogram buckets")
+        .install_recorder()
+        .expect("Failed to install metrics recorder")
+}
+
+// Set the slow query threshold, 0 turning the warning off; must run before the first query
+pub fn set_slow_query_ms(ms: u64) {
+    let _ = SLOW_QUERY.set((ms > 0).then(|| Duration::from_millis(ms)));
+}
+
+// Run a query future and record how long it took, labeled by operation. Queries slower than
+// the slow query threshold are also logged.
+pub async fn time_query<T, F>(operation: &'static str, query: F) -> T where F: Future<Output = T> {
+    let start = Instant::now();
+    let result = query.await;
+    record_query(operation, start.elapsed());
+    result
+}
+
+// Record database time measured by the caller, for work that is not a single future
+pub fn record_query(operation: &'static str, elapsed: Duration) {
+    metrics::histogram!(QUERY_DURATION, "operation" => operation).record(elapsed.as_secs_f64());
+
+    let threshold = *SLOW_QUERY.get_or_init(|| Some(Duration::from_millis(DEFAULT_SLOW_QUERY_MS)));
+    if threshold.is_some_and(|threshold| elapsed > threshold) {
+        let duration_ms = (elapsed.as_secs_f64() * 1_000_000.0).round() / 1000.0;
+        tracing::warn!(operation, duration_ms, "Slow query");
+    }
+}
+
+// Expose collected metrics in the Prometheus text format
+pub async fn render(State(state): State<AppState>) -> String {
+    state.metrics.render()
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+use sqlx::{ PgPool, postgres::PgListener };
+
+// Channel the `books_changed` trigger publishes on
+pub const CHANNEL: &str = "books_changed";
+
+// What changed according to the database
+#[derive(Clone, Copy, Debug)]
+pub enum BookChange {
+    Book(i32),
+    // The connection dropped, so notifications may have been missed
+    All,
+}
+
+// Longest wait between attempts to (re)subscribe
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// Call `on_change` for every `books_changed` notification for as long as the process runs. The
+// listener holds one pool connection for good, so only spawn it when something needs the events.
+pub fn spawn_listener<F>(pool: PgPool, on_change: F) where F: Fn(BookChange) + Send + 'static {
+    tokio::spawn(async move {
+        let mut listener = subscribe(&pool).await;
+        // Writes made before the subscription went through were never announced
+        on_change(BookChange::All);
+
+        loop {
+            let change = match listener.try_recv().await {
+                Ok(Some(notification)) =>
+                    match notification.payload().parse() {
+                        Ok(id) => BookChange::Book(id),
+                        Err(_) => {
+                            tracing::warn!("Ignoring books_changed payload {:?}", notification.payload());
+                            continue;
+                        }
+                    }
+                // PgListener reconnects on its own, but anything sent meanwhile is lost
+                Ok(None) => BookChange::All,
+                Err(e) => {
+                    tracing::error!("Books listener error: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            on_change(change);
+        }
+    });
+}
+
+// Connect and `LISTEN`, retrying with exponential backoff until both succeed
+async fn subscribe(pool: &PgPool) -> PgListener {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match PgListener::connect_with(pool).await {
+            Ok(mut listener) =>
+                match listener.listen(CHANNEL).await {
+                    Ok(()) => return listener,
+                    Err(e) => tracing::error!("Books listener subscribe error, retrying in {:?}: {}", backoff, e),
+                }
+            Err(e) => tracing::error!("Books listener connect error, retrying in {:?}: {}", backoff, e),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,89 @@
+use axum::{ http::HeaderValue, response::Response };
+use serde::Serialize;
+
+use crate::{ error::ApiError, i18n::Message };
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 100;
+pub const DEFAULT_RESULT_CAP: i64 = 1000;
+// Far beyond any sensible response, and keeps `cap + 1` from overflowing
+pub const MAX_RESULT_CAP: i64 = 100_000;
+// Set on a list response that `HARD_RESULT_CAP` cut short
+pub const TRUNCATED_HEADER: &str = "x-truncated";
+
+// A validated `?limit=&offset=` window
+#[derive(Clone, Copy, Debug)]
+pub struct Page {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Page {
+    pub fn new(limit: Option<i64>, offset: Option<i64>) -> Result<Self, ApiError> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+        let offset = offset.unwrap_or(0);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(ApiError::BadRequest(Message::LimitOutOfRange { max: MAX_LIMIT }));
+        }
+        if offset < 0 {
+            return Err(ApiError::BadRequest(Message::NegativeOffset));
+        }
+        Ok(Page { limit, offset })
+    }
+}
+
+// Standard envelope for paginated lists
+#[derive(Serialize)]
+pub struct Paginated<T> {
+    pub data: Vec<T>,
+    pub limit: i64,
+    pub offset: i64,
+    pub total: i64,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(data: Vec<T>, page: Page, total: i64) -> Self {
+        Paginated { data, limit: page.limit, offset: page.offset, total }
+    }
+}
+
+// A list or detail body, wrapped as `{"data": ...}` like `Paginated` when asked for with `?envelope=`
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Enveloped<T> {
+    Wrapped {
+        data: T,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        truncated: bool,
+    },
+    Raw(T),
+}
+
+impl<T> Enveloped<T> {
+    pub fn new(body: T, envelope: bool) -> Self {
+        Enveloped::capped(body, envelope, false)
+    }
+
+    // A list body that reports `"truncated": true` when `truncated` is set. A cut-short list is
+    // always wrapped, since a bare array has nowhere to say so.
+    pub fn capped(body: T, envelope: bool, truncated: bool) -> Self {
+        if envelope || truncated { Enveloped::Wrapped { data: body, truncated } } else { Enveloped::Raw(body) }
+    }
+}
+
+// Cut rows fetched with `LIMIT cap + 1` down to `cap`, saying whether any were dropped
+pub fn truncate<T>(mut rows: Vec<T>, cap: i64) -> (Vec<T>, bool) {
+    let cap = usize::try_from(cap).unwrap_or(usize::MAX);
+    let truncated = rows.len() > cap;
+    rows.truncate(cap);
+    (rows, truncated)
+}
+
+// Add `x-truncated: true` to a list response that was cut short, so clients can tell without
+// reading the body
+pub fn mark_truncated(mut response: Response, truncated: bool) -> Response {
+    if truncated {
+        response.headers_mut().insert(TRUNCATED_HEADER, HeaderValue::from_static("true"));
+    }
+    response
+}
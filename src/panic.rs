@@ -0,0 +1,25 @@
+use std::any::Any;
+use axum::response::{ IntoResponse, Response };
+use tower_http::catch_panic::CatchPanicLayer;
+
+use crate::error::ApiError;
+
+type PanicHandler = fn(Box<dyn Any + Send + 'static>) -> Response;
+
+// Turn a panicking handler into a logged 500 instead of a dropped connection
+pub fn layer() -> CatchPanicLayer<PanicHandler> {
+    CatchPanicLayer::custom(handle_panic as PanicHandler)
+}
+
+fn handle_panic(payload: Box<dyn Any + Send + 'static>) -> Response {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    };
+
+    tracing::error!(panic = %message, "Handler panicked");
+    ApiError::Internal.into_response()
+}
@@ -0,0 +1,35 @@
+use std::io::Cursor;
+use axum::{
+    extract::State,
+    http::header,
+    response::{ IntoResponse, Response },
+};
+use image::{ ImageFormat, Luma };
+use qrcode::QrCode;
+
+use crate::{ AppState, book_not_found, error::{ ApiError, BookId }, repository };
+
+// Smallest side of the generated image, in pixels
+const MIN_SIZE: u32 = 256;
+
+// PNG QR code linking to the book's detail URL
+pub async fn book_qr(State(state): State<AppState>, BookId(id): BookId) -> Result<Response, ApiError> {
+    if repository::find_by_id(&state.pool, id).await?.is_none() {
+        return Err(book_not_found(id));
+    }
+
+    let url = format!("{}/books/{}", state.public_base_url, id);
+    let png = render_png(&url).map_err(|e| {
+        tracing::error!("QR code error: {}", e);
+        ApiError::Internal
+    })?;
+
+    Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response())
+}
+
+fn render_png(data: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let image = QrCode::new(data.as_bytes())?.render::<Luma<u8>>().min_dimensions(MIN_SIZE, MIN_SIZE).build();
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
@@ -0,0 +1,19 @@
+use axum::{ extract::FromRequestParts, http::request::Parts };
+
+use crate::{ AppState, error::ApiError, i18n::Message };
+
+// Guard for handlers that change data, rejecting the request while `READ_ONLY` is set. Being an
+// extractor rather than a layer, it only runs once a route and method matched, so unsupported
+// methods still get a 405 and unknown paths a 404.
+pub struct Writable;
+
+impl FromRequestParts<AppState> for Writable {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        if state.read_only {
+            return Err(ApiError::ServiceUnavailable(Message::ReadOnly));
+        }
+        Ok(Writable)
+    }
+}
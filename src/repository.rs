@@ -0,0 +1,555 @@
+use std::time::Duration;
+use futures_util::stream::BoxStream;
+use sqlx::PgPool;
+
+use crate::{
+    Book,
+    Bookpayload,
+    CheckoutItem,
+    DuplicateEntry,
+    DuplicateGroup,
+    Missing,
+    Reservation,
+    Review,
+    covers::Cover,
+    metrics::time_query,
+    pagination::Page,
+    sort::Sort,
+};
+
+// Round-trip a trivial query to check the database is reachable
+pub async fn ping(pool: &PgPool) -> Result<(), sqlx::Error> {
+    time_query("ping", sqlx::query("SELECT 1").execute(pool)).await.map(|_| ())
+}
+
+// Insert a sentinel book inside a transaction and roll it back, exercising INSERT ... RETURNING
+pub async fn self_test(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx
+        ::query_as::<_, Book>("INSERT INTO books (title, stock) VALUES ('__startup_self_test__', 0) RETURNING *")
+        .fetch_one(&mut *tx).await?;
+    tx.rollback().await
+}
+
+// Fetch books in the given order, optionally only those credited to `author`, at most `limit`
+pub async fn list(pool: &PgPool, sort: Sort, author: Option<&str>, limit: i64) -> Result<Vec<Book>, sqlx::Error> {
+    let sql = format!(
+        "SELECT * FROM books \
+         WHERE $1::text IS NULL OR EXISTS (SELECT 1 FROM unnest(authors) a WHERE lower(a) = lower($1)) {} \
+         LIMIT $2",
+        sort.order_by()
+    );
+    time_query("list", sqlx::query_as::<_, Book>(&sql).bind(author).bind(limit).fetch_all(pool)).await
+}
+
+// Every book in ID order, read from a cursor one row at a time instead of all at once
+pub fn stream_all(pool: &PgPool) -> BoxStream<'_, Result<Book, sqlx::Error>> {
+    sqlx::query_as::<_, Book>("SELECT * FROM books ORDER BY id").fetch(pool)
+}
+
+// Books missing the given metadata, one page at a time, with the total number of matches
+pub async fn incomplete(pool: &PgPool, missing: Missing, page: Page) -> Result<(Vec<Book>, i64), sqlx::Error> {
+    let condition = match missing {
+        Missing::Author => "author IS NULL",
+        Missing::PublishedDate => "published_date IS NULL",
+        Missing::Any => "author IS NULL OR published_date IS NULL",
+    };
+
+    let sql = format!("SELECT * FROM books WHERE {} ORDER BY id LIMIT $1 OFFSET $2", condition);
+    let books = time_query(
+        "incomplete",
+        sqlx::query_as::<_, Book>(&sql).bind(page.limit).bind(page.offset).fetch_all(pool)
+    ).await?;
+
+    let count_sql = format!("SELECT COUNT(*) FROM books WHERE {}", condition);
+    let total = time_query("incomplete_count", sqlx::query_scalar::<_, i64>(&count_sql).fetch_one(pool)).await?;
+
+    Ok((books, total))
+}
+
+#[derive(sqlx::FromRow)]
+struct DuplicateRow {
+    title: String,
+    author: Option<String>,
+    ids: Vec<i32>,
+    stocks: Vec<i32>,
+}
+
+// Books whose title and authors match after lowercasing and collapsing whitespace, largest
+// groups first, each group's books in ID order, at most `limit` groups
+pub async fn duplicates(pool: &PgPool, limit: i64) -> Result<Vec<DuplicateGroup>, sqlx::Error> {
+    let rows = time_query(
+        "duplicates",
+        sqlx
+            ::query_as::<_, DuplicateRow>(
+                "SELECT (array_agg(title ORDER BY id))[1] AS title, \
+                        (array_agg(author ORDER BY id))[1] AS author, \
+                        array_agg(id ORDER BY id) AS ids, \
+                        array_agg(stock ORDER BY id) AS stocks \
+                 FROM books \
+                 GROUP BY lower(regexp_replace(btrim(title), '\\s+', ' ', 'g')), \
+                          lower(regexp_replace(btrim(array_to_string(authors, '|')), '\\s+', ' ', 'g')) \
+                 HAVING count(*) > 1 \
+                 ORDER BY count(*) DESC, 1 \
+                 LIMIT $1"
+            )
+            .bind(limit)
+            .fetch_all(pool)
+    ).await?;
+
+    Ok(
+        rows
+            .into_iter()
+            .map(|row| DuplicateGroup {
+                title: row.title,
+                author: row.author,
+                books: row.ids
+                    .into_iter()
+                    .zip(row.stocks)
+                    .map(|(id, stock)| DuplicateEntry { id, stock })
+                    .collect(),
+            })
+            .collect()
+    )
+}
+
+// The most recently added books, newest first
+pub async fn latest(pool: &PgPool, limit: i64) -> Result<Vec<Book>, sqlx::Error> {
+    time_query(
+        "latest",
+        sqlx
+            ::query_as::<_, Book>("SELECT * FROM books ORDER BY created_at DESC, id DESC LIMIT $1")
+            .bind(limit)
+            .fetch_all(pool)
+    ).await
+}
+
+// Distinct titles starting with `prefix`, ignoring case, alphabetically
+pub async fn title_completions(pool: &PgPool, prefix: &str, limit: i64) -> Result<Vec<String>, sqlx::Error> {
+    // Treat LIKE wildcards typed by the user as literal characters
+    let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+    time_query(
+        "title_completions",
+        sqlx
+            ::query_scalar::<_, String>(
+                "SELECT DISTINCT title FROM books WHERE title ILIKE $1 ESCAPE '\\' ORDER BY title LIMIT $2"
+            )
+            .bind(pattern)
+            .bind(limit)
+            .fetch_all(pool)
+    ).await
+}
+
+// Fetch a single book, `None` when the ID does not exist
+pub async fn find_by_id(pool: &PgPool, id: i32) -> Result<Option<Book>, sqlx::Error> {
+    time_query(
+        "find_by_id",
+        sqlx::query_as::<_, Book>("SELECT * FROM books WHERE id = $1").bind(id).fetch_optional(pool)
+    ).await
+}
+
+// Reviews of a book, newest first
+pub async fn reviews_for(pool: &PgPool, book_id: i32) -> Result<Vec<Review>, sqlx::Error> {
+    time_query(
+        "reviews_for",
+        sqlx
+            ::query_as::<_, Review>(
+                "SELECT id, rating, comment, created_at FROM reviews WHERE book_id = $1 ORDER BY created_at DESC, id DESC"
+            )
+            .bind(book_id)
+            .fetch_all(pool)
+    ).await
+}
+
+// Names of the tags attached to a book, alphabetically
+pub async fn tags_for(pool: &PgPool, book_id: i32) -> Result<Vec<String>, sqlx::Error> {
+    time_query(
+        "tags_for",
+        sqlx
+            ::query_scalar::<_, String>(
+                "SELECT t.name FROM tags t JOIN book_tags bt ON bt.tag_id = t.id WHERE bt.book_id = $1 ORDER BY t.name"
+            )
+            .bind(book_id)
+            .fetch_all(pool)
+    ).await
+}
+
+// Make `names` the complete tag set of a book, creating tags that do not exist yet.
+// Returns the resulting tags alphabetically, `None` when the book does not exist.
+pub async fn set_tags(pool: &PgPool, book_id: i32, names: &[String]) -> Result<Option<Vec<String>>, sqlx::Error> {
+    time_query("set_tags", async {
+        let mut tx = pool.begin().await?;
+
+        // Locking the book serializes concurrent replacements of its tags
+        let book = sqlx
+            ::query_scalar::<_, i32>("SELECT id FROM books WHERE id = $1 FOR UPDATE")
+            .bind(book_id)
+            .fetch_optional(&mut *tx).await?;
+        if book.is_none() {
+            return Ok(None);
+        }
+
+        sqlx
+            ::query("INSERT INTO tags (name) SELECT unnest($1::text[]) ON CONFLICT (name) DO NOTHING")
+            .bind(names)
+            .execute(&mut *tx).await?;
+        sqlx
+            ::query(
+                "DELETE FROM book_tags WHERE book_id = $1 \
+                 AND tag_id NOT IN (SELECT id FROM tags WHERE name = ANY($2))"
+            )
+            .bind(book_id)
+            .bind(names)
+            .execute(&mut *tx).await?;
+        sqlx
+            ::query(
+                "INSERT INTO book_tags (book_id, tag_id) SELECT $1, id FROM tags WHERE name = ANY($2) \
+                 ON CONFLICT DO NOTHING"
+            )
+            .bind(book_id)
+            .bind(names)
+            .execute(&mut *tx).await?;
+        let tags = sqlx
+            ::query_scalar::<_, String>(
+                "SELECT t.name FROM tags t JOIN book_tags bt ON bt.tag_id = t.id WHERE bt.book_id = $1 ORDER BY t.name"
+            )
+            .bind(book_id)
+            .fetch_all(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(Some(tags))
+    }).await
+}
+
+// Other books sharing at least one author with `id` (ignoring case), closest publication dates first
+pub async fn similar(pool: &PgPool, id: i32, limit: i64) -> Result<Vec<Book>, sqlx::Error> {
+    time_query(
+        "similar",
+        sqlx
+            ::query_as::<_, Book>(
+                "SELECT b.* FROM books b JOIN books source ON source.id = $1 \
+                 WHERE b.id <> source.id \
+                   AND EXISTS (SELECT 1 FROM unnest(b.authors) x JOIN unnest(source.authors) y ON lower(x) = lower(y)) \
+                 ORDER BY abs(b.published_date - source.published_date) NULLS LAST, b.title, b.id \
+                 LIMIT $2"
+            )
+            .bind(id)
+            .bind(limit)
+            .fetch_all(pool)
+    ).await
+}
+
+// Insert a new book and return the stored row
+pub async fn create(pool: &PgPool, payload: &Bookpayload) -> Result<Book, sqlx::Error> {
+    time_query(
+        "create",
+        sqlx
+            ::query_as::<_, Book>(
+                "INSERT INTO books (title, authors, isbn, published_date, stock) VALUES ($1, $2, $3, $4, $5) RETURNING *"
+            )
+            .bind(payload.title.trim())
+            .bind(payload.authors())
+            // Checked by `Bookpayload::validate` before any write
+            .bind(payload.isbn().ok().flatten())
+            .bind(payload.published_date)
+            .bind(payload.stock)
+            .fetch_one(pool)
+    ).await
+}
+
+// Replace a book's fields, `None` when the ID does not exist
+pub async fn update(pool: &PgPool, id: i32, payload: &Bookpayload) -> Result<Option<Book>, sqlx::Error> {
+    time_query(
+        "update",
+        sqlx
+            ::query_as::<_, Book>(
+                "UPDATE books SET title = $1, authors = $2, isbn = $3, published_date = $4, stock = $5 WHERE id = $6 RETURNING *"
+            )
+            .bind(payload.title.trim())
+            .bind(payload.authors())
+            // Checked by `Bookpayload::validate` before any write
+            .bind(payload.isbn().ok().flatten())
+            .bind(payload.published_date)
+            .bind(payload.stock)
+            .bind(id)
+            .fetch_optional(pool)
+    ).await
+}
+
+// Overwrite a book's stock, `None` when the book does not exist
+pub async fn set_stock(pool: &PgPool, id: i32, stock: i32) -> Result<Option<Book>, sqlx::Error> {
+    time_query(
+        "set_stock",
+        sqlx
+            ::query_as::<_, Book>("UPDATE books SET stock = $1, updated_at = NOW() WHERE id = $2 RETURNING *")
+            .bind(stock)
+            .bind(id)
+            .fetch_optional(pool)
+    ).await
+}
+
+// Add `delta` to a book's stock unless the result would fall outside `0..=max_stock`,
+// `None` when nothing was updated
+pub async fn adjust_stock(pool: &PgPool, id: i32, delta: i32, max_stock: i32) -> Result<Option<Book>, sqlx::Error> {
+    time_query(
+        "adjust_stock",
+        sqlx
+            ::query_as::<_, Book>(
+                // Widen before adding so out-of-range results are rejected instead of overflowing
+                "UPDATE books SET stock = stock + $1 WHERE id = $2 AND stock::bigint + $1 BETWEEN 0 AND $3 RETURNING *"
+            )
+            .bind(delta)
+            .bind(id)
+            .bind(max_stock)
+            .fetch_optional(pool)
+    ).await
+}
+
+pub enum ReserveOutcome {
+    Reserved(Reservation),
+    NotFound,
+    InsufficientStock { available: i32 },
+}
+
+// Hold `quantity` copies of a book. The row lock taken by `FOR UPDATE` serializes concurrent
+// reservations of the same book, so the stock check and decrement cannot interleave.
+pub async fn reserve(pool: &PgPool, book_id: i32, quantity: i32) -> Result<ReserveOutcome, sqlx::Error> {
+    time_query("reserve", async {
+        let mut tx = pool.begin().await?;
+
+        let stock = sqlx
+            ::query_scalar::<_, i32>("SELECT stock FROM books WHERE id = $1 FOR UPDATE")
+            .bind(book_id)
+            .fetch_optional(&mut *tx).await?;
+        let Some(stock) = stock else {
+            return Ok(ReserveOutcome::NotFound);
+        };
+        if stock < quantity {
+            return Ok(ReserveOutcome::InsufficientStock { available: stock });
+        }
+
+        sqlx
+            ::query("UPDATE books SET stock = stock - $1 WHERE id = $2")
+            .bind(quantity)
+            .bind(book_id)
+            .execute(&mut *tx).await?;
+        let reservation = sqlx
+            ::query_as::<_, Reservation>(
+                "INSERT INTO reservations (book_id, quantity) VALUES ($1, $2) RETURNING *"
+            )
+            .bind(book_id)
+            .bind(quantity)
+            .fetch_one(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(ReserveOutcome::Reserved(reservation))
+    }).await
+}
+
+pub enum CheckoutOutcome {
+    // The books touched by the checkout, in the order they first appear in the manifest
+    Completed(Vec<Book>),
+    NotFound { item: usize, id: i32 },
+    InsufficientStock { item: usize, id: i32, available: i32 },
+}
+
+// Take every item of a manifest out of stock in one transaction. Nothing is applied unless all
+// items succeed; the first failing item (by position in the manifest) is reported.
+pub async fn checkout(pool: &PgPool, items: &[CheckoutItem]) -> Result<CheckoutOutcome, sqlx::Error> {
+    time_query("checkout", async {
+        let mut tx = pool.begin().await?;
+
+        // Lock every row up front in ID order so concurrent checkouts cannot deadlock
+        let ids: Vec<i32> = items.iter().map(|item| item.id).collect();
+        sqlx
+            ::query("SELECT id FROM books WHERE id = ANY($1) ORDER BY id FOR UPDATE")
+            .bind(&ids)
+            .execute(&mut *tx).await?;
+
+        let mut books: Vec<Book> = Vec::new();
+        for (index, item) in items.iter().enumerate() {
+            let updated = sqlx
+                ::query_as::<_, Book>(
+                    "UPDATE books SET stock = stock - $1 WHERE id = $2 AND stock >= $1 RETURNING *"
+                )
+                .bind(item.quantity)
+                .bind(item.id)
+                .fetch_optional(&mut *tx).await?;
+
+            let Some(book) = updated else {
+                let stock = sqlx
+                    ::query_scalar::<_, i32>("SELECT stock FROM books WHERE id = $1")
+                    .bind(item.id)
+                    .fetch_optional(&mut *tx).await?;
+                // Dropping `tx` rolls back the items applied so far
+                return Ok(match stock {
+                    Some(available) => CheckoutOutcome::InsufficientStock { item: index, id: item.id, available },
+                    None => CheckoutOutcome::NotFound { item: index, id: item.id },
+                });
+            };
+
+            // A book listed twice keeps its latest stock
+            match books.iter_mut().find(|existing| existing.id == book.id) {
+                Some(existing) => {
+                    *existing = book;
+                }
+                None => books.push(book),
+            }
+        }
+
+        tx.commit().await?;
+        Ok(CheckoutOutcome::Completed(books))
+    }).await
+}
+
+pub struct ExpiryOutcome {
+    pub expired: i64,
+    // Books whose stale reservations were left active because restocking them would exceed the ceiling
+    pub blocked: Vec<BlockedRestock>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct BlockedRestock {
+    pub book_id: i32,
+    pub reservations: i64,
+    pub quantity: i64,
+}
+
+// Mark active reservations older than `ttl` as expired and return their copies to stock. A book
+// whose copies would push its stock above `max_stock` keeps its reservations active instead, so
+// expiry never loses inventory; those are reported as blocked.
+pub async fn expire_reservations(pool: &PgPool, ttl: Duration, max_stock: i32) -> Result<ExpiryOutcome, sqlx::Error> {
+    time_query("expire_reservations", async {
+        let mut tx = pool.begin().await?;
+        // `NOW()` is fixed for the transaction, so every statement sees the same stale set
+        let ttl = ttl.as_secs_f64();
+
+        // Lock the affected books in ID order so their stock cannot move between the check and the restock
+        sqlx
+            ::query(
+                "SELECT id FROM books WHERE id IN ( \
+                     SELECT book_id FROM reservations \
+                     WHERE status = 'active' AND created_at < NOW() - make_interval(secs => $1) \
+                 ) ORDER BY id FOR UPDATE"
+            )
+            .bind(ttl)
+            .execute(&mut *tx).await?;
+
+        let blocked = sqlx
+            ::query_as::<_, BlockedRestock>(
+                "SELECT r.book_id, COUNT(*) AS reservations, SUM(r.quantity) AS quantity \
+                 FROM reservations r JOIN books b ON b.id = r.book_id \
+                 WHERE r.status = 'active' AND r.created_at < NOW() - make_interval(secs => $1) \
+                 GROUP BY r.book_id, b.stock \
+                 HAVING b.stock + SUM(r.quantity) > $2 \
+                 ORDER BY r.book_id"
+            )
+            .bind(ttl)
+            .bind(max_stock)
+            .fetch_all(&mut *tx).await?;
+        let blocked_ids: Vec<i32> = blocked.iter().map(|book| book.book_id).collect();
+
+        let expired = sqlx
+            ::query_scalar::<_, i64>(
+                "WITH expired AS ( \
+                     UPDATE reservations SET status = 'expired' \
+                     WHERE status = 'active' AND created_at < NOW() - make_interval(secs => $1) \
+                       AND book_id <> ALL($2) \
+                     RETURNING book_id, quantity \
+                 ), restocked AS ( \
+                     UPDATE books b SET stock = b.stock + freed.quantity \
+                     FROM (SELECT book_id, SUM(quantity)::int AS quantity FROM expired GROUP BY book_id) freed \
+                     WHERE b.id = freed.book_id \
+                 ) \
+                 SELECT COUNT(*) FROM expired"
+            )
+            .bind(ttl)
+            .bind(&blocked_ids)
+            .fetch_one(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(ExpiryOutcome { expired, blocked })
+    }).await
+}
+
+// Delete a book, returning whether a row was removed
+pub async fn delete(pool: &PgPool, id: i32) -> Result<bool, sqlx::Error> {
+    time_query("delete", sqlx::query("DELETE FROM books WHERE id = $1").bind(id).execute(pool)).await.map(
+        |result| result.rows_affected() > 0
+    )
+}
+
+// Store or replace a book's cover, returning `false` when the book does not exist
+pub async fn save_cover(pool: &PgPool, book_id: i32, content_type: &str, data: &[u8]) -> Result<bool, sqlx::Error> {
+    time_query(
+        "save_cover",
+        sqlx
+            ::query(
+                "INSERT INTO book_covers (book_id, content_type, data) SELECT id, $2, $3 FROM books WHERE id = $1 \
+                 ON CONFLICT (book_id) DO UPDATE SET content_type = EXCLUDED.content_type, data = EXCLUDED.data, updated_at = NOW()"
+            )
+            .bind(book_id)
+            .bind(content_type)
+            .bind(data)
+            .execute(pool)
+    ).await.map(|result| result.rows_affected() > 0)
+}
+
+// Fetch a book's cover, `None` when no cover was uploaded
+pub async fn find_cover(pool: &PgPool, book_id: i32) -> Result<Option<Cover>, sqlx::Error> {
+    time_query(
+        "find_cover",
+        sqlx
+            ::query_as::<_, Cover>("SELECT content_type, data FROM book_covers WHERE book_id = $1")
+            .bind(book_id)
+            .fetch_optional(pool)
+    ).await
+}
+
+// Delete a book and return the removed row, `None` when the ID does not exist
+pub async fn delete_returning(pool: &PgPool, id: i32) -> Result<Option<Book>, sqlx::Error> {
+    time_query(
+        "delete_returning",
+        sqlx::query_as::<_, Book>("DELETE FROM books WHERE id = $1 RETURNING *").bind(id).fetch_optional(pool)
+    ).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    #[ignore = "requires a Postgres server in DATABASE_URL"]
+    async fn concurrent_reservations_never_oversell(pool: PgPool) {
+        let book_id: i32 = sqlx
+            ::query_scalar("INSERT INTO books (title, stock) VALUES ('Contended', 5) RETURNING id")
+            .fetch_one(&pool).await
+            .unwrap();
+
+        let attempts: Vec<_> = (0..20)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move { reserve(&pool, book_id, 1).await.unwrap() })
+            })
+            .collect();
+
+        let mut reserved = 0;
+        for attempt in attempts {
+            match attempt.await.unwrap() {
+                ReserveOutcome::Reserved(_) => {
+                    reserved += 1;
+                }
+                ReserveOutcome::InsufficientStock { available } => assert_eq!(available, 0),
+                ReserveOutcome::NotFound => panic!("book disappeared"),
+            }
+        }
+
+        let stock: i32 = sqlx
+            ::query_scalar("SELECT stock FROM books WHERE id = $1")
+            .bind(book_id)
+            .fetch_one(&pool).await
+            .unwrap();
+        assert_eq!(reserved, 5);
+        assert_eq!(stock, 0);
+    }
+}
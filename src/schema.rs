@@ -0,0 +1,14 @@
+use axum::Json;
+use schemars::{ Schema, schema_for };
+
+use crate::{ Book, Bookpayload };
+
+// JSON Schema of a book as returned by the API
+pub async fn book() -> Json<Schema> {
+    Json(schema_for!(Book))
+}
+
+// JSON Schema of the body accepted by create and update
+pub async fn book_payload() -> Json<Schema> {
+    Json(schema_for!(Bookpayload))
+}
@@ -0,0 +1,102 @@
+use std::{ sync::{ Arc, atomic::{ AtomicUsize, Ordering } }, time::Duration };
+use axum::{ extract::{ Request, State }, middleware::Next, response::Response };
+use tokio::sync::{ oneshot, watch };
+
+// Number of requests currently being handled
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Decrements the counter even when the request future is dropped mid-flight
+struct InFlightGuard(InFlight);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        (self.0).0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Middleware counting requests for the duration of their handler
+pub async fn track(State(in_flight): State<InFlight>, request: Request, next: Next) -> Response {
+    in_flight.0.fetch_add(1, Ordering::SeqCst);
+    let _guard = InFlightGuard(in_flight);
+    next.run(request).await
+}
+
+// Lets background tasks notice that shutdown has begun
+#[derive(Clone)]
+pub struct Stopping(watch::Receiver<bool>);
+
+impl Stopping {
+    // Resolve once `serve` has received a shutdown signal
+    pub async fn wait(&mut self) {
+        // An error means `serve` is gone, which is also a reason to stop
+        let _ = self.0.wait_for(|stopping| *stopping).await;
+    }
+}
+
+// The sender half goes to `serve`, clones of the `Stopping` half to background tasks
+pub fn stopping() -> (watch::Sender<bool>, Stopping) {
+    let (sender, receiver) = watch::channel(false);
+    (sender, Stopping(receiver))
+}
+
+// Resolve on Ctrl+C or SIGTERM
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix
+            ::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv().await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+// Serve until a shutdown signal, then give in-flight requests up to `grace` to finish.
+// `stop` is flipped as soon as the signal arrives so background tasks can wind down too.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    app: axum::Router,
+    in_flight: InFlight,
+    grace: Duration,
+    stop: watch::Sender<bool>
+) {
+    let (signalled_tx, signalled_rx) = oneshot::channel();
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        signal().await;
+        let _ = stop.send(true);
+        let _ = signalled_tx.send(());
+    });
+
+    let deadline = async {
+        if signalled_rx.await.is_err() {
+            // The server stopped on its own before any signal arrived
+            return std::future::pending().await;
+        }
+        tracing::info!("Shutting down, waiting up to {}s for {} in-flight requests", grace.as_secs(), in_flight.count());
+        tokio::time::sleep(grace).await;
+    };
+
+    tokio::select! {
+        result = server => result.expect("Server error"),
+        _ = deadline => {
+            tracing::warn!("Shutdown timed out after {}s, abandoning {} in-flight requests", grace.as_secs(), in_flight.count());
+        }
+    }
+}
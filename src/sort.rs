@@ -0,0 +1,59 @@
+use std::{ fmt, str::FromStr };
+
+use crate::i18n::Message;
+
+// Columns the list endpoint may be sorted by
+const FIELDS: &[&str] = &["id", "title", "author", "published_date", "stock", "created_at"];
+
+// Sortable columns that may be NULL
+const NULLABLE: &[&str] = &["author", "published_date"];
+
+// A validated `?sort=` value such as `title` or `-created_at`
+#[derive(Clone, Copy, Debug)]
+pub struct Sort {
+    field: &'static str,
+    descending: bool,
+}
+
+impl Sort {
+    // `ORDER BY` clause for this sort, with `id` as a tie-breaker so the order is stable.
+    // Rows missing the sorted value always come last; Postgres would put them first when descending.
+    pub fn order_by(&self) -> String {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        let nulls = if NULLABLE.contains(&self.field) { " NULLS LAST" } else { "" };
+        if self.field == "id" {
+            format!("ORDER BY id {}", direction)
+        } else {
+            format!("ORDER BY {} {}{}, id ASC", self.field, direction, nulls)
+        }
+    }
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort { field: "id", descending: false }
+    }
+}
+
+impl FromStr for Sort {
+    type Err = Message;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (name, descending) = match value.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (value, false),
+        };
+
+        FIELDS.iter()
+            .find(|field| **field == name)
+            .map(|field| Sort { field, descending })
+            .ok_or_else(|| Message::UnknownSort { name: name.to_string(), fields: FIELDS.join(", ") })
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.descending { "-" } else { "" }, self.field)
+    }
+}
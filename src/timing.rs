@@ -0,0 +1,16 @@
+use std::time::Instant;
+use axum::{ extract::Request, http::HeaderValue, middleware::Next, response::Response };
+
+const HEADER: &str = "x-response-time-ms";
+
+// Middleware setting `X-Response-Time-Ms` to how long the request took inside the server
+pub async fn response_time(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let mut response = next.run(request).await;
+
+    let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+    if let Ok(value) = HeaderValue::from_str(&format!("{:.3}", elapsed_ms)) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}